@@ -0,0 +1,31 @@
+#[macro_use]
+extern crate criterion;
+extern crate algfinder;
+
+use criterion::Criterion;
+
+use algfinder::*;
+
+use std::sync::mpsc::channel;
+
+fn search(c: &mut Criterion) {
+    use algfinder::Turn::*;
+
+    // Fixed so every run searches the same tree. The shortest solution is
+    // seven turns, so no depth stops early.
+    let scramble = parse_algorithm("R U2 R' U' R U' R'").unwrap();
+    let cube = Cube::solved_state().apply(&scramble);
+    let allowed_turns = [U, U_, U2, D, D_, D2, L, L_, L2, R, R_, R2, F, F_, F2, B, B_, B2];
+
+    c.bench_function("search_bounded depth 6", move |b| {
+        b.iter(|| {
+            // The search stops early if nobody is receiving
+            let (tx, rx) = channel();
+            search_bounded(cube, &Cube::solved_state(), &allowed_turns, 6, tx);
+            rx
+        })
+    });
+}
+
+criterion_group!(benches, search);
+criterion_main!(benches);
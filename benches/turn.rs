@@ -0,0 +1,78 @@
+#[macro_use]
+extern crate criterion;
+extern crate algfinder;
+
+use criterion::{black_box, Criterion};
+
+use algfinder::*;
+
+// The facelets of the three columns of a face. Each facelet takes three bits, in rows of three.
+const COLUMN0: u32 = 0b000_000_111_000_000_111_000_000_111;
+const COLUMN2: u32 = COLUMN0 << 6;
+
+// Rotating a face through tables, to compare with the shifts Cube::turn uses. Each of the
+// three rows of a face is 9 bits, and each table holds where those bits end up for one row.
+struct RowTables([[u32; 512]; 3]);
+
+impl RowTables {
+    // Filled in by turning a cube whose right face only has one row set, so the tables
+    // rotate exactly like Cube::turn does
+    fn new() -> RowTables {
+        let mut tables = [[0; 512]; 3];
+
+        for (row, table) in tables.iter_mut().enumerate() {
+            for (bits, rotated) in table.iter_mut().enumerate() {
+                let cube = Cube { up: 0, down: 0, left: 0, right: (bits as u32) << (9 * row),
+                                  front: 0, back: 0 };
+                *rotated = cube.turn(Turn::R).right;
+            }
+        }
+
+        RowTables(tables)
+    }
+
+    fn rotate_face(&self, face: u32) -> u32 {
+        self.0[0][(face & 0x1ff) as usize] | self.0[1][(face >> 9 & 0x1ff) as usize] |
+        self.0[2][(face >> 18 & 0x1ff) as usize]
+    }
+
+    // Cube::turn(Turn::R) with the face rotated through the tables. Not inlined, as
+    // Cube::turn can't be from another crate, so both benchmarks pay for a call.
+    #[inline(never)]
+    fn right(&self, cube: &Cube) -> Cube {
+        Cube {
+            up: (cube.up & !COLUMN2) | (cube.front & COLUMN2),
+            down: (cube.down & !COLUMN2) | (cube.back & COLUMN2),
+            left: cube.left,
+            right: self.rotate_face(cube.right),
+            front: (cube.front & !COLUMN2) | (cube.down & COLUMN2),
+            back: (cube.back & !COLUMN2) | (cube.up & COLUMN2),
+        }
+    }
+}
+
+fn turn(c: &mut Criterion) {
+    c.bench_function("turn every variant", |b| {
+        b.iter(|| {
+            ALL_TURNS.iter().fold(black_box(Cube::solved_state()), |cube, &turn| cube.turn(turn))
+        })
+    });
+
+    let cube = Cube::solved_state().turn_str("R U F' L2 D B'").unwrap();
+    let tables = RowTables::new();
+
+    // Same results, so only the speed differs
+    assert_eq!((0..4).fold(cube, |cube, _| tables.right(&cube)), cube);
+    assert_eq!(tables.right(&cube), cube.turn(Turn::R));
+
+    c.bench_function("R 100 times with shifts", move |b| {
+        b.iter(|| (0..100).fold(black_box(cube), |cube, _| cube.turn(Turn::R)))
+    });
+
+    c.bench_function("R 100 times with row tables", move |b| {
+        b.iter(|| (0..100).fold(black_box(cube), |cube, _| tables.right(&cube)))
+    });
+}
+
+criterion_group!(benches, turn);
+criterion_main!(benches);
@@ -1,9 +1,21 @@
+extern crate rand;
 extern crate rayon;
 
+use self::rand::Rng;
 use self::rayon::prelude::*;
+use self::rayon::ThreadPoolBuilder;
 
+use std::collections::{HashMap, HashSet};
+use std::collections::hash_map::Entry;
 use std::fmt;
-use std::sync::mpsc::Sender;
+use std::iter::FromIterator;
+use std::ops::Deref;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Sender};
+use std::thread;
+use std::time::Instant;
 
 /*
 Cube layout
@@ -40,9 +52,46 @@ const MASK012: u32 = PIECE0 | PIECE1 | PIECE2;
 const MASK036: u32 = PIECE0 | PIECE3 | PIECE6;
 const MASK147: u32 = PIECE1 | PIECE4 | PIECE7;
 const MASK258: u32 = PIECE2 | PIECE5 | PIECE8;
+const MASK345: u32 = PIECE3 | PIECE4 | PIECE5;
 const MASK678: u32 = PIECE6 | PIECE7 | PIECE8;
 
-#[derive(Clone, Copy, Debug)]
+// Where to find each facelet of the URFDLB faces in Kociemba's layout
+const FACELET_ORDER: [[usize; 9]; 6] = [[0, 1, 2, 3, 4, 5, 6, 7, 8],
+                                        [6, 3, 0, 7, 4, 1, 8, 5, 2],
+                                        [0, 1, 2, 3, 4, 5, 6, 7, 8],
+                                        [0, 1, 2, 3, 4, 5, 6, 7, 8],
+                                        [2, 5, 8, 1, 4, 7, 0, 3, 6],
+                                        [8, 7, 6, 5, 4, 3, 2, 1, 0]];
+
+const FACELET_NAMES: [char; 6] = ['U', 'R', 'F', 'D', 'L', 'B'];
+
+// Indices into the facelet string of every corner, U or D sticker first,
+// in the order URF, UFL, ULB, UBR, DFR, DLF, DBL, DRB
+const CORNER_FACELETS: [[usize; 3]; 8] = [[8, 9, 20],
+                                          [6, 18, 38],
+                                          [0, 36, 47],
+                                          [2, 45, 11],
+                                          [29, 26, 15],
+                                          [27, 44, 24],
+                                          [33, 53, 42],
+                                          [35, 17, 51]];
+
+// Every edge in the order UR, UF, UL, UB, DR, DF, DL, DB, FR, FL, BL, BR
+const EDGE_FACELETS: [[usize; 2]; 12] = [[5, 10],
+                                         [7, 19],
+                                         [3, 37],
+                                         [1, 46],
+                                         [32, 16],
+                                         [28, 25],
+                                         [30, 43],
+                                         [34, 52],
+                                         [23, 12],
+                                         [21, 41],
+                                         [50, 39],
+                                         [48, 14]];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Cube<T = u32> {
     pub up: T,
     pub down: T,
@@ -53,6 +102,7 @@ pub struct Cube<T = u32> {
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Color {
     Grey = 0,
     White = 1,
@@ -63,7 +113,43 @@ pub enum Color {
     Orange = 6,
 }
 
-#[derive(Debug, Clone, Copy)]
+// In the order returned by faces, so face as usize indexes it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Face {
+    Up = 0,
+    Down = 1,
+    Left = 2,
+    Right = 3,
+    Front = 4,
+    Back = 5,
+}
+
+pub const ALL_FACES: [Face; 6] = [Face::Up, Face::Down, Face::Left, Face::Right, Face::Front,
+                                  Face::Back];
+
+// The axis a turn turns around, named after the faces it goes through
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Axis {
+    UD,
+    LR,
+    FB,
+}
+
+// A mirror placed between two opposite faces, swapping them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Mirror {
+    LR,
+    UD,
+    FB,
+}
+
+// Every color but grey, in the order colors counts them
+const COLORS: [Color; 6] = [Color::White, Color::Yellow, Color::Green, Color::Blue, Color::Red,
+                            Color::Orange];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Turn {
     U = 0b0,
     U_ = 0b1,
@@ -86,14 +172,122 @@ pub enum Turn {
     M = 0b10000000,
     M_ = 0b10000001,
     M2 = 0b10000010,
+    S = 0b100000000,
+    S_ = 0b100000001,
+    S2 = 0b100000010,
+    E = 0b1000000000,
+    E_ = 0b1000000001,
+    E2 = 0b1000000010,
+    X = 0b10000000000,
+    X_ = 0b10000000001,
+    X2 = 0b10000000010,
+    Y = 0b100000000000,
+    Y_ = 0b100000000001,
+    Y2 = 0b100000000010,
+    Z = 0b1000000000000,
+    Z_ = 0b1000000000001,
+    Z2 = 0b1000000000010,
+}
+
+// Which faces of a cube match a pattern, a bit for each in the order of ALL_FACES.
+// A turn leaves some faces as they were, so after one only the others are checked again,
+// which lets a search carry what a node matched down to its children.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FaceMatches(u8);
+
+// A sequence of turns, displayed space separated.
+// Derefs to a slice, so it can be passed wherever a &[Turn] is expected.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Algorithm(pub Vec<Turn>);
+
+// Pieces numbered in the order of CORNER_FACELETS and EDGE_FACELETS.
+// Twists count how far the U or D sticker of a corner is from the U or D face,
+// an edge is flipped when its first sticker isn't on the first facelet of its position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Cubies {
+    pub corners: [usize; 8],
+    pub twists: [usize; 8],
+    pub edges: [usize; 12],
+    pub flips: [usize; 12],
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CommutatorForm {
+    // A B A' B', written [A, B]
+    Commutator(Algorithm, Algorithm),
+    // A B A', written [A: B]
+    Conjugate(Algorithm, Algorithm),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    InvalidTurn(String),
+}
+
+// A face holding a chunk that is no color, only possible when building cubes by hand
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidFace {
+    pub face: u32,
+    pub index: usize,
+    pub chunk: u8,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FaceletError {
+    WrongLength(usize),
+    InvalidCharacter(char),
+    // A face letter not occurring exactly nine times
+    WrongCount(char, usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BytesError {
+    WrongLength(usize),
+    // The face with this index has bits set beyond its nine facelets
+    UnusedBits(usize),
 }
 
-pub type Algorithm = Vec<Turn>;
+// Why a search can never reach the goal, found by check_feasible
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Infeasible {
+    // The starting state can't be reached by turning a solved cube
+    UnsolvableStart,
+    // Nor can the fully colored goal
+    UnsolvableGoal,
+    // The goal has more stickers of these colors than the starting state
+    MissingColors(Vec<Color>),
+    // The goal needs a facelet changed that none of the allowed turns move
+    Unreachable,
+}
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum SearchResult {
     Algorithm(Algorithm),
+    // An algorithm reaching the goal with this index, sent instead by search_any
+    GoalAlgorithm(usize, Algorithm),
     Depth(usize),
+    // Every depth up to and including this one has been searched
+    Exhausted(usize),
+    // The deadline passed while searching this depth
+    TimedOut(usize),
+    // Sent when a depth has been searched completely, after its algorithms
+    Stats {
+        depth: usize,
+        // Algorithms sent for this depth, so zero tells a finished depth without any
+        found: usize,
+        nodes: u64,
+        elapsed_ms: u64,
+    },
+    // Sent after the Stats of every depth but the first and last. The nodes grew by
+    // branching from the depth before, and growing as much again the next depth should take
+    // about ms.
+    Estimate {
+        depth: usize,
+        branching: f64,
+        ms: u64,
+    },
 }
 
 impl fmt::Display for Turn {
@@ -122,750 +316,4670 @@ impl fmt::Display for Turn {
             M => "M",
             M_ => "M'",
             M2 => "M2",
+            S => "S",
+            S_ => "S'",
+            S2 => "S2",
+            E => "E",
+            E_ => "E'",
+            E2 => "E2",
+            X => "x",
+            X_ => "x'",
+            X2 => "x2",
+            Y => "y",
+            Y_ => "y'",
+            Y2 => "y2",
+            Z => "z",
+            Z_ => "z'",
+            Z2 => "z2",
         };
 
         write!(f, "{}", s)
     }
 }
 
-impl fmt::Display for Color {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        use self::Color::*;
+// Outer face turns first, then slices and rotations.
+// Every variant of Turn must be listed here.
+pub const ALL_TURNS: [Turn; 36] = [Turn::U, Turn::U_, Turn::U2, Turn::D, Turn::D_, Turn::D2,
+                                   Turn::L, Turn::L_, Turn::L2, Turn::R, Turn::R_, Turn::R2,
+                                   Turn::F, Turn::F_, Turn::F2, Turn::B, Turn::B_, Turn::B2,
+                                   Turn::M, Turn::M_, Turn::M2, Turn::S, Turn::S_, Turn::S2,
+                                   Turn::E, Turn::E_, Turn::E2, Turn::X, Turn::X_, Turn::X2,
+                                   Turn::Y, Turn::Y_, Turn::Y2, Turn::Z, Turn::Z_, Turn::Z2];
+
+impl Turn {
+    // Number of clockwise quarter turns
+    fn quarter_turns(self) -> u16 {
+        match self as u16 & 0b11 {
+            0 => 1,
+            1 => 3,
+            _ => 2,
+        }
+    }
 
-        let c = match *self {
-            Grey => '_',
-            White => 'W',
-            Yellow => 'Y',
-            Green => 'G',
-            Blue => 'B',
-            Red => 'R',
-            Orange => 'O',
-        };
+    pub fn inverse(self) -> Turn {
+        self.with_quarter_turns(4 - self.quarter_turns()).unwrap()
+    }
 
-        write!(f, "{}", c)
+    pub fn is_rotation(self) -> bool {
+        use self::Turn::*;
+
+        matches!(self, X | X_ | X2 | Y | Y_ | Y2 | Z | Z_ | Z2)
     }
-}
 
-impl<'a> Cube<Vec<Color>> {
-    fn face_from_colors(colors: &[Color]) -> u32 {
-        let mut face = 0;
+    pub fn is_slice(self) -> bool {
+        use self::Turn::*;
 
-        for (i, &color) in colors.iter().enumerate() {
-            face |= (color as u32) << (3 * i);
-        }
+        matches!(self, M | M_ | M2 | S | S_ | S2 | E | E_ | E2)
+    }
 
-        face
+    // Like U2, turning a half turn
+    pub fn is_half_turn(self) -> bool {
+        self.quarter_turns() == 2
     }
 
-    // Assumes the colors are layouted correctly
-    pub fn pack(&self) -> Cube {
-        Cube {
-            up: Self::face_from_colors(&self.up),
-            down: Self::face_from_colors(&self.down),
-            left: Self::face_from_colors(&self.left),
-            right: Self::face_from_colors(&self.right),
-            front: Self::face_from_colors(&self.front),
-            back: Self::face_from_colors(&self.back),
+    pub fn axis(self) -> Axis {
+        use self::Turn::*;
+
+        match self {
+            U | U_ | U2 | D | D_ | D2 | E | E_ | E2 | Y | Y_ | Y2 => Axis::UD,
+            L | L_ | L2 | R | R_ | R2 | M | M_ | M2 | X | X_ | X2 => Axis::LR,
+            F | F_ | F2 | B | B_ | B2 | S | S_ | S2 | Z | Z_ | Z2 => Axis::FB,
         }
     }
-}
 
-impl Cube {
-    // Yellow on top, green in front
-    pub fn solved_state() -> Self {
-        Cube {
-            up: 0b010010010010010010010010010,
-            down: 0b001001001001001001001001001,
-            left: 0b101101101101101101101101101,
-            right: 0b110110110110110110110110110,
-            front: 0b011011011011011011011011011,
-            back: 0b100100100100100100100100100,
+    // The faces whose stickers the turn moves, a bit for each in the order of ALL_FACES.
+    // An outer turn leaves the opposite face alone and a slice the two faces it is between.
+    pub fn changed_faces(self) -> u8 {
+        use self::Turn::*;
+
+        match self {
+            U | U_ | U2 => 0b111101,
+            D | D_ | D2 => 0b111110,
+            L | L_ | L2 => 0b110111,
+            R | R_ | R2 => 0b111011,
+            F | F_ | F2 => 0b011111,
+            B | B_ | B2 => 0b101111,
+            M | M_ | M2 => 0b110011,
+            S | S_ | S2 => 0b001111,
+            E | E_ | E2 => 0b111100,
+            X | X_ | X2 | Y | Y_ | Y2 | Z | Z_ | Z2 => 0b111111,
         }
     }
 
-    fn faces(&self) -> [u32; 6] {
-        [self.up, self.down, self.left, self.right, self.front, self.back]
+    // Outer layers turned, a slice turn being worth turning the two faces around it
+    fn face_turns(self) -> usize {
+        if self.is_rotation() {
+            0
+        } else if self.is_slice() {
+            2
+        } else {
+            1
+        }
     }
 
-    fn colors_in_face(face: u32) -> ([u8; 6], [u8; 6]) {
-        let (mut corners, mut edges) = ([0; 6], [0; 6]);
+    pub fn same_face(self, other: Turn) -> bool {
+        self as u16 ^ other as u16 <= 0b11
+    }
 
-        for &corner in &[0, 2, 6, 8] {
-            let col = (face >> (3 * corner)) & 0b111;
+    // The turn of the same face doing the given number of clockwise quarter turns
+    fn with_quarter_turns(self, quarter_turns: u16) -> Option<Turn> {
+        use self::Turn::*;
 
-            if col > 0 {
-                corners[col as usize - 1] += 1;
-            }
+        let turns = match self {
+            U | U_ | U2 => [U, U_, U2],
+            D | D_ | D2 => [D, D_, D2],
+            L | L_ | L2 => [L, L_, L2],
+            R | R_ | R2 => [R, R_, R2],
+            F | F_ | F2 => [F, F_, F2],
+            B | B_ | B2 => [B, B_, B2],
+            M | M_ | M2 => [M, M_, M2],
+            S | S_ | S2 => [S, S_, S2],
+            E | E_ | E2 => [E, E_, E2],
+            X | X_ | X2 => [X, X_, X2],
+            Y | Y_ | Y2 => [Y, Y_, Y2],
+            Z | Z_ | Z2 => [Z, Z_, Z2],
+        };
+
+        match quarter_turns % 4 {
+            0 => None,
+            1 => Some(turns[0]),
+            2 => Some(turns[2]),
+            _ => Some(turns[1]),
         }
+    }
+}
 
-        for &edge in &[1, 3, 4, 5, 7] {
-            let col = (face >> (3 * edge)) & 0b111;
+// The turn seen in a mirror placed between the left and right faces.
+// Mirroring reverses every turn, except that M and x are measured from the
+// left and right faces which swap places, so they keep their direction.
+fn mirror(turn: Turn) -> Turn {
+    use self::Turn::*;
 
-            if col > 0 {
-                edges[col as usize - 1] += 1;
-            }
-        }
+    let quarter_turns = turn.inverse().quarter_turns();
 
-        (corners, edges)
+    match turn {
+        M | M_ | M2 | X | X_ | X2 => turn,
+        R | R_ | R2 => L.with_quarter_turns(quarter_turns).unwrap(),
+        L | L_ | L2 => R.with_quarter_turns(quarter_turns).unwrap(),
+        _ => turn.inverse(),
     }
+}
 
-    fn colors(&self) -> ([u8; 6], [u8; 6]) {
-        let add = |xss: [u8; 6], xs: [u8; 6]| {
-            [xss[0] + xs[0],
-             xss[1] + xs[1],
-             xss[2] + xs[2],
-             xss[3] + xs[3],
-             xss[4] + xs[4],
-             xss[5] + xs[5]]
-        };
+// The 48 symmetries of the cube as what they do to each of ALL_TURNS:
+// the 24 rotations, each with and without mirroring
+fn symmetries() -> Vec<Vec<Turn>> {
+    let solved = Cube::solved_state();
+    let rotations = rotations();
 
-        self.faces()
-            .iter()
-            .map(|&face| Self::colors_in_face(face))
-            .fold(([0; 6], [0; 6]),
-                  |(css, ess), (cs, es)| (add(css, cs), add(ess, es)))
+    let mut symmetries = Vec::with_capacity(2 * rotations.len());
+
+    for rotation in &rotations {
+        let undo = invert_algorithm(rotation);
+
+        // Rotating, turning and rotating back is a single turn of another face
+        let rotated: Vec<Turn> = ALL_TURNS.iter()
+            .map(|&turn| {
+                let conjugated = solved.apply(rotation).turn(turn).apply(&undo);
+                *ALL_TURNS.iter().find(|&&other| solved.turn(other) == conjugated).unwrap()
+            })
+            .collect();
+
+        let rotate = |turn: Turn| rotated[ALL_TURNS.iter().position(|&t| t == turn).unwrap()];
+
+        symmetries.push(rotated.clone());
+        symmetries.push(ALL_TURNS.iter().map(|&turn| rotate(mirror(turn))).collect());
     }
 
-    pub fn missing_colors(&self, pattern: &Cube) -> Vec<Color> {
-        use Color::*;
+    symmetries
+}
 
-        let mut missing = Vec::new();
+// The 24 orientations of the cube, each as the fewest rotations reaching it
+pub fn rotations() -> Vec<Algorithm> {
+    use self::Turn::*;
 
-        let colors = [White, Yellow, Green, Blue, Red, Orange];
+    let solved = Cube::solved_state();
 
-        let (from_corners, from_edges) = self.colors();
-        let (to_corners, to_edges) = pattern.colors();
+    let mut rotations = vec![Algorithm::default()];
+    let mut i = 0;
 
-        for &color in &colors {
-            let i = color as usize - 1;
+    while i < rotations.len() {
+        for &rotation in &[X, Y, Z] {
+            let next: Algorithm = rotations[i].iter().cloned().chain(Some(rotation)).collect();
 
-            if from_corners[i] < to_corners[i] || from_edges[i] < to_edges[i] {
-                missing.push(color);
+            if rotations.iter().all(|r| solved.apply(r) != solved.apply(&next)) {
+                rotations.push(next);
             }
         }
 
-        missing
+        i += 1;
     }
 
-    fn matches_face(face: u32, pattern: u32) -> bool {
-        let grey = Color::Grey as u32;
+    rotations
+}
 
-        ((pattern & PIECE0) == grey || (pattern & PIECE0 == face & PIECE0)) &&
-        ((pattern & PIECE1) == grey || (pattern & PIECE1 == face & PIECE1)) &&
-        ((pattern & PIECE2) == grey || (pattern & PIECE2 == face & PIECE2)) &&
-        ((pattern & PIECE3) == grey || (pattern & PIECE3 == face & PIECE3)) &&
-        ((pattern & PIECE4) == grey || (pattern & PIECE4 == face & PIECE4)) &&
-        ((pattern & PIECE5) == grey || (pattern & PIECE5 == face & PIECE5)) &&
-        ((pattern & PIECE6) == grey || (pattern & PIECE6 == face & PIECE6)) &&
-        ((pattern & PIECE7) == grey || (pattern & PIECE7 == face & PIECE7)) &&
-        ((pattern & PIECE8) == grey || (pattern & PIECE8 == face & PIECE8))
+// The alphabetically first way of writing the algorithm under any symmetry
+fn symmetric_form(alg: &[Turn], symmetries: &[Vec<Turn>]) -> String {
+    symmetries.iter()
+        .map(|symmetry| {
+            let mapped: Algorithm = alg.iter()
+                .map(|&turn| symmetry[ALL_TURNS.iter().position(|&t| t == turn).unwrap()])
+                .collect();
+
+            format_algorithm(&mapped)
+        })
+        .min()
+        .unwrap()
+}
+
+// Merges consecutive turns of the same face and drops those that cancel out.
+// Working on a stack means a cancellation exposes the previous turn to the next one,
+// so the result is fully simplified after a single pass.
+pub fn simplify(alg: &[Turn]) -> Algorithm {
+    let mut simplified: Vec<Turn> = Vec::with_capacity(alg.len());
+
+    for &turn in alg {
+        match simplified.pop() {
+            Some(last) if last.same_face(turn) => {
+                let quarter_turns = last.quarter_turns() + turn.quarter_turns();
+
+                if let Some(merged) = last.with_quarter_turns(quarter_turns) {
+                    simplified.push(merged);
+                }
+            }
+            Some(last) => {
+                simplified.push(last);
+                simplified.push(turn);
+            }
+            None => simplified.push(turn),
+        }
     }
 
-    fn matches(&self, other: &Cube) -> bool {
-        ((self.up & other.up) == other.up) && ((self.down & other.down) == other.down) &&
-        ((self.left & other.left) == other.left) &&
-        ((self.right & other.right) == other.right) &&
-        ((self.front & other.front) == other.front) &&
-        ((self.back & other.back) == other.back) && Self::matches_face(self.up, other.up) &&
-        Self::matches_face(self.down, other.down) &&
-        Self::matches_face(self.left, other.left) &&
-        Self::matches_face(self.right, other.right) &&
-        Self::matches_face(self.front, other.front) &&
-        Self::matches_face(self.back, other.back)
+    Algorithm(simplified)
+}
+
+// The algorithm undoing the given one
+pub fn invert_algorithm(alg: &[Turn]) -> Algorithm {
+    alg.iter().rev().map(|turn| turn.inverse()).collect()
+}
+
+// Short sequences many algorithms are built from, longer ones before their prefixes
+pub const TRIGGERS: [&[Turn]; 6] = [
+    // Sexy move and reverse sexy move
+    &[Turn::R, Turn::U, Turn::R_, Turn::U_],
+    &[Turn::R_, Turn::U_, Turn::R, Turn::U],
+    // Sledgehammer and hedgeslammer
+    &[Turn::R_, Turn::F, Turn::R, Turn::F_],
+    &[Turn::F, Turn::R_, Turn::F_, Turn::R],
+    &[Turn::R, Turn::U, Turn::R_],
+    &[Turn::R, Turn::U_, Turn::R_],
+];
+
+// The triggers in the algorithm as their start and index into TRIGGERS, from the left.
+// Of overlapping triggers the one starting first wins, or the first in TRIGGERS if they
+// start together, and the others are not reported.
+pub fn find_triggers(alg: &[Turn]) -> Vec<(usize, usize)> {
+    let mut found = Vec::new();
+    let mut i = 0;
+
+    while i < alg.len() {
+        match TRIGGERS.iter().position(|trigger| alg[i..].starts_with(trigger)) {
+            Some(trigger) => {
+                found.push((i, trigger));
+                i += TRIGGERS[trigger].len();
+            }
+            None => i += 1,
+        }
     }
 
-    fn rotate_face(face: u32) -> u32 {
-        let part4 = face & PIECE4;
+    found
+}
 
-        let part05 = (face & (PIECE0 | PIECE5)) << SHIFT2;
-        let part1 = (face & PIECE1) << SHIFT4;
-        let part2 = (face & PIECE2) << SHIFT6;
+// Splits the simplified algorithm into a commutator, or failing that a conjugate.
+// Only splits where no turns cancel between the parts are found.
+pub fn commutator_form(alg: &[Turn]) -> Option<CommutatorForm> {
+    let alg = simplify(alg);
+    let n = alg.len();
 
-        let part38 = (face & (PIECE3 | PIECE8)) >> SHIFT2;
-        let part7 = (face & PIECE7) >> SHIFT4;
-        let part6 = (face & PIECE6) >> SHIFT6;
+    for i in 1..n / 2 {
+        let j = n / 2;
+        let (a, b) = (&alg[..i], &alg[i..j]);
 
-        part4 | part05 | part1 | part2 | part38 | part7 | part6
+        let mut commutator = a.to_vec();
+        commutator.extend(b);
+        commutator.extend(invert_algorithm(a));
+        commutator.extend(invert_algorithm(b));
+
+        if commutator == *alg {
+            return Some(CommutatorForm::Commutator(a.to_vec().into(), b.to_vec().into()));
+        }
     }
 
-    fn rotate_face_(face: u32) -> u32 {
-        let part4 = face & PIECE4;
+    // The longest setup leaves the shortest core
+    for i in (1..n.div_ceil(2)).rev() {
+        let (a, b) = (&alg[..i], &alg[i..n - i]);
 
-        let part16 = (face & (PIECE1 | PIECE6)) << SHIFT2;
-        let part3 = (face & PIECE3) << SHIFT4;
-        let part0 = (face & PIECE0) << SHIFT6;
+        if *invert_algorithm(a) == alg[n - i..] {
+            return Some(CommutatorForm::Conjugate(a.to_vec().into(), b.to_vec().into()));
+        }
+    }
 
-        let part27 = (face & (PIECE2 | PIECE7)) >> SHIFT2;
-        let part5 = (face & PIECE5) >> SHIFT4;
-        let part8 = (face & PIECE8) >> SHIFT6;
+    None
+}
 
-        part4 | part16 | part3 | part0 | part27 | part5 | part8
+// Space separated turns
+pub fn format_algorithm(alg: &[Turn]) -> String {
+    alg.iter().map(|turn| turn.to_string()).collect::<Vec<_>>().join(" ")
+}
+
+// Whitespace separated turns in the same notation as format_algorithm.
+// Lines may end in // comments, like the depth headers of exported results.
+pub fn parse_algorithm(s: &str) -> Result<Algorithm, ParseError> {
+    s.lines()
+        .flat_map(|line| line.split("//").next().unwrap_or("").split_whitespace())
+        .map(|token| token.parse())
+        .collect()
+}
+
+// False if the goal needs a facelet changed that none of the allowed turns move.
+// Passing is no guarantee that the goal can be reached.
+pub fn reachability_check(from: &Cube, to: &Cube, allowed_turns: &[Turn]) -> bool {
+    let (from_faces, to_faces) = (from.faces(), to.faces());
+
+    (0..6).all(|face| {
+        (0..9).all(|i| {
+            let have = (from_faces[face] >> (3 * i)) & 0b111;
+            let want = (to_faces[face] >> (3 * i)) & 0b111;
+
+            if want == Color::Grey as u32 || have == want {
+                return true;
+            }
+
+            // Mark just this facelet and see if any allowed turn moves it
+            let mut faces = [0; 6];
+            faces[face] = 1 << (3 * i);
+            let marked = Cube::from_faces(faces);
+
+            allowed_turns.iter().any(|&turn| marked.turn(turn) != marked)
+        })
+    })
+}
+
+// Checks the cheap reasons a search could never reach the goal before searching.
+// Passing is no guarantee either, as the allowed turns may not reach every state.
+pub fn check_feasible(from: &Cube, to: &Cube, allowed_turns: &[Turn]) -> Result<(), Infeasible> {
+    // Only fully colored states can be checked
+    if from.is_fully_specified() && !from.is_solvable() {
+        return Err(Infeasible::UnsolvableStart);
     }
 
-    fn rotate_face2(face: u32) -> u32 {
-        ((face & PIECE0) << SHIFT8) | ((face & PIECE1) << SHIFT6) | ((face & PIECE2) << SHIFT4) |
-        ((face & PIECE3) << SHIFT2) |
-        ((face & PIECE4)) | ((face & PIECE5) >> SHIFT2) | ((face & PIECE6) >> SHIFT4) |
-        ((face & PIECE7) >> SHIFT6) | ((face & PIECE8) >> SHIFT8)
+    if to.is_fully_specified() && !to.is_solvable() {
+        return Err(Infeasible::UnsolvableGoal);
     }
 
+    let missing = from.missing_colors(to);
 
-    fn right(&self) -> Self {
-        Cube {
-            up: (self.up & !MASK258) | (self.front & MASK258),
-            down: (self.down & !MASK258) | (self.back & MASK258),
-            left: self.left,
-            right: Self::rotate_face(self.right),
-            front: (self.front & !MASK258) | (self.down & MASK258),
-            back: (self.back & !MASK258) | (self.up & MASK258),
-        }
+    if !missing.is_empty() {
+        return Err(Infeasible::MissingColors(missing));
     }
 
-    fn right_(&self) -> Self {
-        Cube {
-            up: (self.up & !MASK258) | (self.back & MASK258),
-            down: (self.down & !MASK258) | (self.front & MASK258),
-            left: self.left,
-            right: Self::rotate_face_(self.right),
-            front: (self.front & !MASK258) | (self.up & MASK258),
-            back: (self.back & !MASK258) | (self.down & MASK258),
-        }
+    if !reachability_check(from, to, allowed_turns) {
+        return Err(Infeasible::Unreachable);
     }
 
-    fn right2(&self) -> Self {
-        Cube {
-            up: (self.up & !MASK258) | (self.down & MASK258),
-            down: (self.down & !MASK258) | (self.up & MASK258),
-            left: self.left,
-            right: Self::rotate_face2(self.right),
-            front: (self.front & !MASK258) | (self.back & MASK258),
-            back: (self.back & !MASK258) | (self.front & MASK258),
+    Ok(())
+}
+
+// Random sequence of outer face turns, never turning two faces of the same axis in a row.
+// Opposite faces commute, so "R L" is no more scrambled than "L R" and in "R L R" the two
+// R turns merge.
+pub fn scramble(len: usize, rng: &mut impl Rng) -> (Cube, Algorithm) {
+    let face_turns = &ALL_TURNS[..18];
+
+    let mut alg: Vec<Turn> = Vec::with_capacity(len);
+
+    while alg.len() < len {
+        let turn = *rng.choose(face_turns).unwrap();
+
+        if alg.last().is_none_or(|last| last.axis() != turn.axis()) {
+            alg.push(turn);
         }
     }
 
+    (Cube::solved_state().apply(&alg), Algorithm(alg))
+}
 
-    fn left(&self) -> Self {
-        Cube {
-            up: (self.up & !MASK036) | (self.back & MASK036),
-            down: (self.down & !MASK036) | (self.front & MASK036),
-            left: Self::rotate_face(self.left),
-            right: self.right,
-            front: (self.front & !MASK036) | (self.up & MASK036),
-            back: (self.back & !MASK036) | (self.down & MASK036),
-        }
+impl Algorithm {
+    // The algorithm undoing this one
+    pub fn inverse(&self) -> Algorithm {
+        invert_algorithm(self)
     }
 
-    fn left_(&self) -> Self {
-        Cube {
-            up: (self.up & !MASK036) | (self.front & MASK036),
-            down: (self.down & !MASK036) | (self.back & MASK036),
-            left: Self::rotate_face_(self.left),
-            right: self.right,
-            front: (self.front & !MASK036) | (self.down & MASK036),
-            back: (self.back & !MASK036) | (self.up & MASK036),
-        }
+    pub fn simplify(&self) -> Algorithm {
+        simplify(self)
     }
 
-    fn left2(&self) -> Self {
-        Cube {
-            up: (self.up & !MASK036) | (self.down & MASK036),
-            down: (self.down & !MASK036) | (self.up & MASK036),
-            left: Self::rotate_face2(self.left),
-            right: self.right,
-            front: (self.front & !MASK036) | (self.back & MASK036),
-            back: (self.back & !MASK036) | (self.front & MASK036),
-        }
+    // The simplified algorithm written out, the same for algorithms like "U U" and "U2"
+    // that only differ in turns that merge or cancel
+    pub fn canonical_string(&self) -> String {
+        format_algorithm(&simplify(self))
     }
 
+    // The lengths below never count rotations.
+    // Half turn metric: outer turns count one and slice turns two, like the outer turns
+    // they equal.
+    pub fn htm(&self) -> usize {
+        self.iter().map(|turn| turn.face_turns()).sum()
+    }
 
-    fn middle(&self) -> Self {
-        Cube {
-            up: (self.up & !MASK147) | (self.back & MASK147),
-            down: (self.down & !MASK147) | (self.front & MASK147),
-            left: self.left,
-            right: self.right,
-            front: (self.front & !MASK147) | (self.up & MASK147),
-            back: (self.back & !MASK147) | (self.down & MASK147),
-        }
+    // Quarter turn metric: as htm, but half turns count double
+    pub fn qtm(&self) -> usize {
+        self.iter().map(|&turn| turn.face_turns() * quarter_turn_cost(turn)).sum()
     }
 
-    fn middle_(&self) -> Self {
-        Cube {
-            up: (self.up & !MASK147) | (self.front & MASK147),
-            down: (self.down & !MASK147) | (self.back & MASK147),
-            left: self.left,
-            right: self.right,
-            front: (self.front & !MASK147) | (self.down & MASK147),
-            back: (self.back & !MASK147) | (self.up & MASK147),
+    // Slice turn metric: outer and slice turns count one
+    pub fn stm(&self) -> usize {
+        self.iter().filter(|turn| !turn.is_rotation()).count()
+    }
+}
+
+impl Deref for Algorithm {
+    type Target = [Turn];
+
+    fn deref(&self) -> &[Turn] {
+        &self.0
+    }
+}
+
+impl From<Vec<Turn>> for Algorithm {
+    fn from(turns: Vec<Turn>) -> Algorithm {
+        Algorithm(turns)
+    }
+}
+
+impl FromIterator<Turn> for Algorithm {
+    fn from_iter<I: IntoIterator<Item = Turn>>(iter: I) -> Algorithm {
+        Algorithm(iter.into_iter().collect())
+    }
+}
+
+impl IntoIterator for Algorithm {
+    type Item = Turn;
+    type IntoIter = ::std::vec::IntoIter<Turn>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a Algorithm {
+    type Item = &'a Turn;
+    type IntoIter = ::std::slice::Iter<'a, Turn>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
+impl fmt::Display for Algorithm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", format_algorithm(self))
+    }
+}
+
+impl FaceMatches {
+    pub fn new(cube: &Cube, pattern: &Cube) -> FaceMatches {
+        FaceMatches::check(0b111111, 0, cube, pattern)
+    }
+
+    // The matches of the cube that results from turning, given those of the cube turned.
+    // Only the faces the turn changed are checked again.
+    pub fn after(self, turn: Turn, cube: &Cube, pattern: &Cube) -> FaceMatches {
+        let changed = turn.changed_faces();
+        FaceMatches::check(changed, self.0 & !changed, cube, pattern)
+    }
+
+    // Whether all faces match after the turn, which is cube.matches(pattern) when self
+    // holds the matches of the cube turned. Stops at the first changed face that fails.
+    pub fn all_after(self, turn: Turn, cube: &Cube, pattern: &Cube) -> bool {
+        let changed = turn.changed_faces();
+        (self.0 | changed) == 0b111111 &&
+        ALL_FACES.iter().enumerate().all(|(i, &face)| {
+            changed & (1 << i) == 0 ||
+            Cube::matches_whole_face(cube.face(face), pattern.face(face))
+        })
+    }
+
+    pub fn all(self) -> bool {
+        self.0 == 0b111111
+    }
+
+    fn check(faces: u8, matched: u8, cube: &Cube, pattern: &Cube) -> FaceMatches {
+        let checked = ALL_FACES.iter().enumerate()
+            .filter(|&(i, &face)| {
+                faces & (1 << i) != 0 &&
+                Cube::matches_whole_face(cube.face(face), pattern.face(face))
+            })
+            .fold(0, |bits, (i, _)| bits | 1 << i);
+        FaceMatches(matched | checked)
+    }
+}
+
+impl Color {
+    // The inverse of Display
+    pub fn from_char(c: char) -> Option<Color> {
+        use self::Color::*;
+
+        match c {
+            '_' => Some(Grey),
+            'W' => Some(White),
+            'Y' => Some(Yellow),
+            'G' => Some(Green),
+            'B' => Some(Blue),
+            'R' => Some(Red),
+            'O' => Some(Orange),
+            _ => None,
         }
     }
 
-    fn middle2(&self) -> Self {
+    // The inverse of casting a color to a number
+    pub fn from_u8(n: u8) -> Option<Color> {
+        use self::Color::*;
+
+        match n {
+            0 => Some(Grey),
+            1 => Some(White),
+            2 => Some(Yellow),
+            3 => Some(Green),
+            4 => Some(Blue),
+            5 => Some(Red),
+            6 => Some(Orange),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::Color::*;
+
+        let c = match *self {
+            Grey => '_',
+            White => 'W',
+            Yellow => 'Y',
+            Green => 'G',
+            Blue => 'B',
+            Red => 'R',
+            Orange => 'O',
+        };
+
+        write!(f, "{}", c)
+    }
+}
+
+impl fmt::Display for CommutatorForm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CommutatorForm::Commutator(ref a, ref b) => {
+                write!(f, "[{}, {}]", a, b)
+            }
+            CommutatorForm::Conjugate(ref a, ref b) => {
+                write!(f, "[{}: {}]", a, b)
+            }
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseError::InvalidTurn(ref turn) => write!(f, "Invalid turn: {}", turn),
+        }
+    }
+}
+
+impl FromStr for Turn {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Turn, ParseError> {
+        // Websites often use a typographic apostrophe for primes
+        let name = s.replace('\u{2019}', "'");
+
+        ALL_TURNS.iter()
+            .cloned()
+            .find(|turn| turn.to_string() == name)
+            .ok_or_else(|| ParseError::InvalidTurn(s.to_string()))
+    }
+}
+
+impl fmt::Display for InvalidFace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid chunk {} in face {} at {}", self.chunk, self.face, self.index)
+    }
+}
+
+impl fmt::Display for FaceletError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FaceletError::WrongLength(len) => {
+                write!(f, "Expected 54 facelets but got {}", len)
+            }
+            FaceletError::InvalidCharacter(c) => write!(f, "Invalid facelet: {}", c),
+            FaceletError::WrongCount(c, count) => {
+                write!(f, "Expected 9 of facelet {} but got {}", c, count)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Infeasible {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Infeasible::UnsolvableStart => {
+                write!(f, "The starting state can't be reached by turning")
+            }
+            Infeasible::UnsolvableGoal => write!(f, "The goal can't be reached by turning"),
+            Infeasible::MissingColors(ref colors) => {
+                let names: Vec<String> =
+                    colors.iter().map(|color| format!("{:?}", color)).collect();
+
+                write!(f, "The starting state has too few {} stickers", names.join(", "))
+            }
+            Infeasible::Unreachable => {
+                write!(f, "The allowed turns never move some facelets the goal needs changed")
+            }
+        }
+    }
+}
+
+impl fmt::Display for BytesError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            BytesError::WrongLength(len) => write!(f, "Expected 24 bytes but got {}", len),
+            BytesError::UnusedBits(face) => write!(f, "Unused bits set in face {}", face),
+        }
+    }
+}
+
+impl Cube<Vec<Color>> {
+    fn face_from_colors(colors: &[Color]) -> u32 {
+        let mut face = 0;
+
+        for (i, &color) in colors.iter().enumerate() {
+            face |= (color as u32) << (3 * i);
+        }
+
+        face
+    }
+
+    // Assumes the colors are layouted correctly
+    pub fn pack(&self) -> Cube {
         Cube {
-            up: (self.up & !MASK147) | (self.down & MASK147),
-            down: (self.down & !MASK147) | (self.up & MASK147),
-            left: self.left,
-            right: self.right,
-            front: (self.front & !MASK147) | (self.back & MASK147),
-            back: (self.back & !MASK147) | (self.front & MASK147),
+            up: Self::face_from_colors(&self.up),
+            down: Self::face_from_colors(&self.down),
+            left: Self::face_from_colors(&self.left),
+            right: Self::face_from_colors(&self.right),
+            front: Self::face_from_colors(&self.front),
+            back: Self::face_from_colors(&self.back),
         }
     }
+}
 
+// Groups 1 to 7 for matches_groups, 0 leaves a facelet out.
+// Laid out like the colors given to pack.
+impl Cube<[u8; 9]> {
+    pub fn pack_groups(&self) -> Cube {
+        let pack_face = |groups: &[u8; 9]| {
+            groups.iter()
+                .enumerate()
+                .fold(0, |face, (i, &group)| face | (group as u32 & 0b111) << (3 * i))
+        };
 
-    fn front(&self) -> Self {
-        let right_to_down = ((self.right & PIECE6) >> SHIFT4) | ((self.right & PIECE7) >> SHIFT6) |
-                            ((self.right & PIECE8) >> SHIFT8);
+        Cube {
+            up: pack_face(&self.up),
+            down: pack_face(&self.down),
+            left: pack_face(&self.left),
+            right: pack_face(&self.right),
+            front: pack_face(&self.front),
+            back: pack_face(&self.back),
+        }
+    }
+}
 
-        let down_to_left = ((self.down & PIECE2) << SHIFT4) | ((self.down & PIECE1) << SHIFT6) |
-                           ((self.down & PIECE0) << SHIFT8);
+impl Cube {
+    // The inverse of pack
+    pub fn unpack(&self) -> Result<Cube<Vec<Color>>, InvalidFace> {
+        let unpack_face = |face| (0..9).map(|i| nth_chunk(i, face)).collect::<Result<_, _>>();
+
+        Ok(Cube {
+            up: unpack_face(self.up)?,
+            down: unpack_face(self.down)?,
+            left: unpack_face(self.left)?,
+            right: unpack_face(self.right)?,
+            front: unpack_face(self.front)?,
+            back: unpack_face(self.back)?,
+        })
+    }
+
+    // The cube seen in the mirror, with every sticker keeping its color
+    pub fn mirror(&self, plane: Mirror) -> Cube {
+        // For each of the URFDLB faces, the face it is mirrored onto and whether its rows
+        // rather than its columns are reversed, in Kociemba's layout
+        let (targets, reverse_rows) = match plane {
+            Mirror::LR => ([0, 4, 2, 3, 1, 5], [false; 6]),
+            Mirror::UD => ([3, 1, 2, 0, 4, 5], [true; 6]),
+            Mirror::FB => ([0, 1, 5, 3, 4, 2], [true, false, false, true, false, false]),
+        };
+
+        let source = self.faces_urfdlb();
+        let mut faces = [0; 6];
+
+        for (k, order) in FACELET_ORDER.iter().enumerate() {
+            for (j, &i) in order.iter().enumerate() {
+                let (row, col) = (j / 3, j % 3);
+                let mirrored =
+                    if reverse_rows[k] { 3 * (2 - row) + col } else { 3 * row + 2 - col };
+
+                let chunk = (source[k] >> (3 * i)) & 0b111;
+                faces[targets[k]] |= chunk << (3 * FACELET_ORDER[targets[k]][mirrored]);
+            }
+        }
 
         Cube {
-            up: (self.up & !MASK678) | (self.left & MASK678),
-            down: (self.down & !MASK012) | right_to_down,
-            left: (self.left & !MASK678) | down_to_left,
-            right: (self.right & !MASK678) | (self.up & MASK678),
-            front: Self::rotate_face(self.front),
-            back: self.back,
+            up: faces[0],
+            down: faces[3],
+            left: faces[4],
+            right: faces[1],
+            front: faces[2],
+            back: faces[5],
         }
     }
 
-    fn front_(&self) -> Self {
-        let left_to_down = ((self.left & PIECE6) >> SHIFT4) | ((self.left & PIECE7) >> SHIFT6) |
-                           ((self.left & PIECE8) >> SHIFT8);
+    // The stickers, as face and index within it, whose colors differ between the cubes
+    pub fn changed_facelets(&self, other: &Cube) -> Vec<(Face, usize)> {
+        let mut changed = Vec::new();
 
-        let down_to_right = ((self.down & PIECE2) << SHIFT4) | ((self.down & PIECE1) << SHIFT6) |
-                            ((self.down & PIECE0) << SHIFT8);
+        for &face in &ALL_FACES {
+            for i in 0..9 {
+                let chunk = |face: u32| (face >> (3 * i)) & 0b111;
+
+                if chunk(self.face(face)) != chunk(other.face(face)) {
+                    changed.push((face, i));
+                }
+            }
+        }
+
+        changed
+    }
 
+    // Every facelet grey, a pattern matching any cube
+    pub fn unknown() -> Self {
         Cube {
-            up: (self.up & !MASK678) | (self.right & MASK678),
-            down: (self.down & !MASK012) | left_to_down,
-            left: (self.left & !MASK678) | (self.up & MASK678),
-            right: (self.right & !MASK678) | down_to_right,
-            front: Self::rotate_face_(self.front),
-            back: self.back,
+            up: 0,
+            down: 0,
+            left: 0,
+            right: 0,
+            front: 0,
+            back: 0,
+        }
+    }
+
+    // Yellow on top, green in front
+    pub fn solved_state() -> Self {
+        Cube {
+            up: 0b010010010010010010010010010,
+            down: 0b001001001001001001001001001,
+            left: 0b101101101101101101101101101,
+            right: 0b110110110110110110110110110,
+            front: 0b011011011011011011011011011,
+            back: 0b100100100100100100100100100,
+        }
+    }
+
+    // Every edge flipped in place, which takes 20 turns, the most any state needs
+    pub fn superflip() -> Self {
+        use self::Turn::*;
+
+        Cube::solved_state().apply(&[U, R2, F, B, R, B2, R, U2, L, B2, R, U_, D_, R2, F, R_, L,
+                                     B2, U2, F2])
+    }
+
+    // Every face a checkerboard of its own color and the opposite one
+    pub fn checkerboard() -> Self {
+        use self::Turn::*;
+
+        Cube::solved_state().apply(&[U2, D2, L2, R2, F2, B2])
+    }
+
+    // Every face a single color, as given by the scheme
+    pub fn solved_with(scheme: &Cube<Color>) -> Self {
+        let solid = |color: Color| (0..9).fold(0, |face, i| face | (color as u32) << (3 * i));
+
+        Cube {
+            up: solid(scheme.up),
+            down: solid(scheme.down),
+            left: solid(scheme.left),
+            right: solid(scheme.right),
+            front: solid(scheme.front),
+            back: solid(scheme.back),
+        }
+    }
+
+    // Equal to solved_state, so turned back into its orientation too
+    pub fn is_solved(&self) -> bool {
+        *self == Cube::solved_state()
+    }
+
+    // Equal to solved_with the scheme
+    pub fn is_solved_with(&self, scheme: &Cube<Color>) -> bool {
+        *self == Cube::solved_with(scheme)
+    }
+
+    pub fn faces(&self) -> [u32; 6] {
+        [self.up, self.down, self.left, self.right, self.front, self.back]
+    }
+
+    // A face chosen at runtime, so code can go through ALL_FACES instead of naming each
+    pub fn face(&self, face: Face) -> u32 {
+        match face {
+            Face::Up => self.up,
+            Face::Down => self.down,
+            Face::Left => self.left,
+            Face::Right => self.right,
+            Face::Front => self.front,
+            Face::Back => self.back,
+        }
+    }
+
+    pub fn face_mut(&mut self, face: Face) -> &mut u32 {
+        match face {
+            Face::Up => &mut self.up,
+            Face::Down => &mut self.down,
+            Face::Left => &mut self.left,
+            Face::Right => &mut self.right,
+            Face::Front => &mut self.front,
+            Face::Back => &mut self.back,
+        }
+    }
+
+    fn from_faces(faces: [u32; 6]) -> Self {
+        Cube {
+            up: faces[0],
+            down: faces[1],
+            left: faces[2],
+            right: faces[3],
+            front: faces[4],
+            back: faces[5],
+        }
+    }
+
+    fn faces_urfdlb(&self) -> [u32; 6] {
+        [self.up, self.right, self.front, self.down, self.left, self.back]
+    }
+
+    // The 54 character facelet string used by Kociemba's solver and others.
+    // Stickers are named after the face whose center has their color,
+    // stickers matching no center, like grey ones, are written as '_'.
+    pub fn to_facelets(&self) -> String {
+        let faces = self.faces_urfdlb();
+        let centers: Vec<Option<Color>> =
+            faces.iter().map(|&face| nth_chunk(4, face).ok()).collect();
+
+        let mut facelets = String::with_capacity(54);
+
+        for (&face, order) in faces.iter().zip(FACELET_ORDER.iter()) {
+            for &i in order {
+                let color = nth_chunk(i, face).ok();
+
+                // Invalid chunks match no center either
+                let center = centers.iter().position(|&center| center.is_some() && center == color);
+
+                facelets.push(match center {
+                    Some(center) => FACELET_NAMES[center],
+                    None => '_',
+                });
+            }
+        }
+
+        facelets
+    }
+
+    // The faces in the order of faces, each as four bytes, least significant first.
+    // Dense enough to store many states, as the format never changes.
+    pub fn to_bytes(&self) -> [u8; 24] {
+        let mut bytes = [0; 24];
+
+        for (i, &face) in self.faces().iter().enumerate() {
+            for j in 0..4 {
+                bytes[4 * i + j] = (face >> (8 * j)) as u8;
+            }
+        }
+
+        bytes
+    }
+
+    // The inverse of to_bytes
+    pub fn from_bytes(bytes: &[u8]) -> Result<Cube, BytesError> {
+        if bytes.len() != 24 {
+            return Err(BytesError::WrongLength(bytes.len()));
+        }
+
+        let mut faces = [0; 6];
+
+        for (i, face) in faces.iter_mut().enumerate() {
+            *face = (0..4).fold(0, |face, j| face | (bytes[4 * i + j] as u32) << (8 * j));
+
+            if *face >> 27 != 0 {
+                return Err(BytesError::UnusedBits(i));
+            }
+        }
+
+        Ok(Cube::from_faces(faces))
+    }
+
+    // Parses the format written by to_facelets.
+    // Faces are given the colors of the solved state, so U is yellow and so on.
+    pub fn from_facelets(facelets: &str) -> Result<Cube, FaceletError> {
+        let names: Vec<char> = facelets.chars().collect();
+
+        if names.len() != 54 {
+            return Err(FaceletError::WrongLength(names.len()));
+        }
+
+        let mut counts = [0; 6];
+
+        for &name in &names {
+            match FACELET_NAMES.iter().position(|&n| n == name) {
+                Some(i) => counts[i] += 1,
+                None => return Err(FaceletError::InvalidCharacter(name)),
+            }
+        }
+
+        for (&name, &count) in FACELET_NAMES.iter().zip(counts.iter()) {
+            if count != 9 {
+                return Err(FaceletError::WrongCount(name, count));
+            }
+        }
+
+        let solved = Cube::solved_state().faces_urfdlb();
+        let mut faces = [0; 6];
+
+        for (k, order) in FACELET_ORDER.iter().enumerate() {
+            for (j, &i) in order.iter().enumerate() {
+                let name = names[9 * k + j];
+                let center = FACELET_NAMES.iter().position(|&n| n == name).unwrap();
+                let color = (solved[center] >> (3 * 4)) & 0b111;
+
+                faces[k] |= color << (3 * i);
+            }
+        }
+
+        Ok(Cube {
+            up: faces[0],
+            down: faces[3],
+            left: faces[4],
+            right: faces[1],
+            front: faces[2],
+            back: faces[5],
+        })
+    }
+
+    // Whether turning can solve the cube, so no piece is twisted, flipped or
+    // swapped on its own. Cubes with grey pieces are never solvable.
+    pub fn is_solvable(&self) -> bool {
+        match self.cubies() {
+            Some(cubies) => {
+                is_permutation(&cubies.corners) && is_permutation(&cubies.edges) &&
+                cubies.twists.iter().sum::<usize>() % 3 == 0 &&
+                cubies.flips.iter().sum::<usize>() % 2 == 0 &&
+                parity(&cubies.corners) == parity(&cubies.edges)
+            }
+            None => false,
+        }
+    }
+
+    // The piece in every position, relative to the centers.
+    // None if a sticker is grey or the stickers of a position make no piece.
+    pub fn cubies(&self) -> Option<Cubies> {
+        let packed = self.faces_urfdlb();
+        let mut centers = [0; 6];
+
+        for (center, &face) in centers.iter_mut().zip(packed.iter()) {
+            *center = (face >> (3 * 4)) & 0b111;
+        }
+
+        // Otherwise a grey face would match its grey stickers like any other color
+        let real_color = |center: u32| {
+            center != Color::Grey as u32 && Color::from_u8(center as u8).is_some()
+        };
+
+        if !centers.iter().all(|&center| real_color(center)) {
+            return None;
+        }
+
+        // The face each sticker belongs on, 0 to 5 in URFDLB order, named like to_facelets
+        // does but without building a string, as predicates call this at every node
+        let mut faces = [0; 54];
+
+        for (k, (&face, order)) in packed.iter().zip(FACELET_ORDER.iter()).enumerate() {
+            for (j, &i) in order.iter().enumerate() {
+                let chunk = (face >> (3 * i)) & 0b111;
+
+                faces[9 * k + j] = centers.iter().position(|&center| center == chunk)?;
+            }
+        }
+
+        // Two centers of the same color
+        if (0..6).any(|face| faces[9 * face + 4] != face) {
+            return None;
+        }
+
+        let mut cubies = Cubies {
+            corners: [0; 8],
+            twists: [0; 8],
+            edges: [0; 12],
+            flips: [0; 12],
+        };
+
+        for (i, position) in CORNER_FACELETS.iter().enumerate() {
+            let colors = [faces[position[0]], faces[position[1]], faces[position[2]]];
+
+            let twisted = colors.iter().position(|&face| face == 0 || face == 3)?;
+
+            cubies.corners[i] = CORNER_FACELETS.iter()
+                .position(|corner| (0..3).all(|k| corner[k] / 9 == colors[(twisted + k) % 3]))?;
+            cubies.twists[i] = twisted;
+        }
+
+        for (i, position) in EDGE_FACELETS.iter().enumerate() {
+            let (a, b) = (faces[position[0]], faces[position[1]]);
+
+            match EDGE_FACELETS.iter().position(|edge| edge[0] / 9 == a && edge[1] / 9 == b) {
+                Some(edge) => cubies.edges[i] = edge,
+                None => {
+                    cubies.edges[i] = EDGE_FACELETS.iter()
+                        .position(|edge| edge[0] / 9 == b && edge[1] / 9 == a)?;
+                    cubies.flips[i] = 1;
+                }
+            }
+        }
+
+        Some(cubies)
+    }
+
+    fn colors_in_face(face: u32) -> ([u8; 6], [u8; 6], [u8; 6]) {
+        // Grey and invalid chunks aren't counted
+        let count = |pieces: &[usize]| {
+            let mut counts = [0; 6];
+
+            for &piece in pieces {
+                match nth_chunk(piece, face) {
+                    Ok(Color::Grey) | Err(_) => {}
+                    Ok(color) => counts[color as usize - 1] += 1,
+                }
+            }
+
+            counts
+        };
+
+        (count(&[0, 2, 6, 8]), count(&[1, 3, 5, 7]), count(&[4]))
+    }
+
+    fn colors(&self) -> ([u8; 6], [u8; 6], [u8; 6]) {
+        let add = |xss: [u8; 6], xs: [u8; 6]| {
+            [xss[0] + xs[0],
+             xss[1] + xs[1],
+             xss[2] + xs[2],
+             xss[3] + xs[3],
+             xss[4] + xs[4],
+             xss[5] + xs[5]]
+        };
+
+        self.faces()
+            .iter()
+            .map(|&face| Self::colors_in_face(face))
+            .fold(([0; 6], [0; 6], [0; 6]),
+                  |(css, ess, mss), (cs, es, ms)| (add(css, cs), add(ess, es), add(mss, ms)))
+    }
+
+    pub fn missing_colors(&self, pattern: &Cube) -> Vec<Color> {
+        let mut missing = Vec::new();
+
+        let (from_corners, from_edges, from_centers) = self.colors();
+        let (to_corners, to_edges, to_centers) = pattern.colors();
+
+        for &color in &COLORS {
+            let i = color as usize - 1;
+
+            if from_corners[i] < to_corners[i] || from_edges[i] < to_edges[i] ||
+               from_centers[i] < to_centers[i] {
+                missing.push(color);
+            }
+        }
+
+        missing
+    }
+
+    // Colors with more stickers of some kind than a cube has room for,
+    // which is four corner stickers, four edge stickers and one center
+    pub fn excess_colors(&self) -> Vec<Color> {
+        let (corners, edges, centers) = self.colors();
+
+        COLORS.iter()
+            .cloned()
+            .filter(|&color| {
+                let i = color as usize - 1;
+                corners[i] > 4 || edges[i] > 4 || centers[i] > 1
+            })
+            .collect()
+    }
+
+    // Colors with every one of their stickers placed
+    pub fn full_colors(&self) -> Vec<Color> {
+        let (corners, edges, centers) = self.colors();
+
+        COLORS.iter()
+            .cloned()
+            .filter(|&color| {
+                let i = color as usize - 1;
+                corners[i] >= 4 && edges[i] >= 4 && centers[i] >= 1
+            })
+            .collect()
+    }
+
+    // True if each of the nine chunks of the face equals the pattern's, or the pattern's
+    // is grey. Takes faces as packed in a Cube, like those returned by faces.
+    pub fn matches_face(face: u32, pattern: u32) -> bool {
+        let grey = Color::Grey as u32;
+
+        ((pattern & PIECE0) == grey || (pattern & PIECE0 == face & PIECE0)) &&
+        ((pattern & PIECE1) == grey || (pattern & PIECE1 == face & PIECE1)) &&
+        ((pattern & PIECE2) == grey || (pattern & PIECE2 == face & PIECE2)) &&
+        ((pattern & PIECE3) == grey || (pattern & PIECE3 == face & PIECE3)) &&
+        ((pattern & PIECE4) == grey || (pattern & PIECE4 == face & PIECE4)) &&
+        ((pattern & PIECE5) == grey || (pattern & PIECE5 == face & PIECE5)) &&
+        ((pattern & PIECE6) == grey || (pattern & PIECE6 == face & PIECE6)) &&
+        ((pattern & PIECE7) == grey || (pattern & PIECE7 == face & PIECE7)) &&
+        ((pattern & PIECE8) == grey || (pattern & PIECE8 == face & PIECE8))
+    }
+
+    // One face of matches, the mask check and then the chunks
+    fn matches_whole_face(face: u32, pattern: u32) -> bool {
+        (face & pattern) == pattern && Self::matches_face(face, pattern)
+    }
+
+    // True if every facelet that isn't grey in the pattern has the same color.
+    // Grey facelets match anything, including grey. Grey is 0, so the masks first check
+    // that every bit set in the pattern is set in the cube, which grey chunks always
+    // pass. That is needed for equal colors but not enough, as 0b011 contains 0b001,
+    // so matches_face then compares the chunks that aren't grey one by one.
+    pub fn matches(&self, other: &Cube) -> bool {
+        ((self.up & other.up) == other.up) && ((self.down & other.down) == other.down) &&
+        ((self.left & other.left) == other.left) &&
+        ((self.right & other.right) == other.right) &&
+        ((self.front & other.front) == other.front) &&
+        ((self.back & other.back) == other.back) && Self::matches_face(self.up, other.up) &&
+        Self::matches_face(self.down, other.down) &&
+        Self::matches_face(self.left, other.left) &&
+        Self::matches_face(self.right, other.right) &&
+        Self::matches_face(self.front, other.front) &&
+        Self::matches_face(self.back, other.back)
+    }
+
+    // The pattern with its colors swapped for every arrangement of the centers that slice
+    // turns and rotations can make, so each has the centers of one arrangement.
+    // Matching any of them is matching the pattern with its colors read relative to the
+    // centers. Needs the six centers in different colors, otherwise just the pattern.
+    pub fn center_recolorings(&self) -> Vec<Cube> {
+        let center = |face: u32| (face >> 12) & 0b111;
+        let centers: Vec<u32> = self.faces().iter().map(|&face| center(face)).collect();
+
+        let anchored = (0..6).all(|i| {
+            centers[i] != Color::Grey as u32 && (0..i).all(|j| centers[j] != centers[i])
+        });
+
+        if !anchored {
+            return vec![*self];
+        }
+
+        rotations()
+            .iter()
+            .map(|rotation| {
+                // Grey and colors on no center stay as they are
+                let mut colors: Vec<u32> = (0..8).collect();
+
+                for (&from, &to) in centers.iter().zip(self.apply(rotation).faces().iter()) {
+                    colors[from as usize] = center(to);
+                }
+
+                let recolor = |face: u32| {
+                    (0..9).fold(0, |recolored, i| {
+                        recolored | colors[((face >> (3 * i)) & 0b111) as usize] << (3 * i)
+                    })
+                };
+
+                let faces = self.faces();
+
+                Cube::from_faces([recolor(faces[0]),
+                                  recolor(faces[1]),
+                                  recolor(faces[2]),
+                                  recolor(faces[3]),
+                                  recolor(faces[4]),
+                                  recolor(faces[5])])
+            })
+            .collect()
+    }
+
+    // Like matches, but with the colors of the pattern relative to its centers
+    pub fn matches_relative(&self, pattern: &Cube) -> bool {
+        pattern.center_recolorings().iter().any(|recolored| self.matches(recolored))
+    }
+
+    // True if the facelets sharing a group in the layout all have the same color
+    pub fn matches_groups(&self, groups: &Cube) -> bool {
+        let mut colors = [None; 8];
+
+        for (&face, &group_face) in self.faces().iter().zip(groups.faces().iter()) {
+            for i in 0..9 {
+                let group = ((group_face >> (3 * i)) & 0b111) as usize;
+
+                if group == 0 {
+                    continue;
+                }
+
+                let color = (face >> (3 * i)) & 0b111;
+
+                match colors[group] {
+                    Some(first) if first != color => return false,
+                    Some(_) => {}
+                    None => colors[group] = Some(color),
+                }
+            }
+        }
+
+        true
+    }
+
+    // True if no facelet has the color it has in the layout, grey forbidding nothing
+    pub fn avoids(&self, forbidden: &Cube) -> bool {
+        self.faces().iter().zip(forbidden.faces().iter()).all(|(&face, &forbidden_face)| {
+            (0..9).all(|i| {
+                let forbidden = (forbidden_face >> (3 * i)) & 0b111;
+
+                forbidden == Color::Grey as u32 || (face >> (3 * i)) & 0b111 != forbidden
+            })
+        })
+    }
+
+    // True if no facelet is grey
+    pub fn is_fully_specified(&self) -> bool {
+        let grey = Color::Grey as u32;
+
+        self.faces().iter().all(|&face| (0..9).all(|i| (face >> (3 * i)) & 0b111 != grey))
+    }
+
+    fn mismatched_in_face(face: u32, pattern: u32) -> usize {
+        let grey = Color::Grey as u32;
+
+        (0..9)
+            .filter(|&i| {
+                let expected = (pattern >> (3 * i)) & 0b111;
+                expected != grey && expected != (face >> (3 * i)) & 0b111
+            })
+            .count()
+    }
+
+    // Number of non-grey facelets in the pattern that this cube doesn't match
+    fn mismatched_facelets(&self, pattern: &Cube) -> usize {
+        Self::mismatched_in_face(self.up, pattern.up) +
+        Self::mismatched_in_face(self.down, pattern.down) +
+        Self::mismatched_in_face(self.left, pattern.left) +
+        Self::mismatched_in_face(self.right, pattern.right) +
+        Self::mismatched_in_face(self.front, pattern.front) +
+        Self::mismatched_in_face(self.back, pattern.back)
+    }
+
+    // Lookup tables for the three rows of a face turn no faster than these shifts,
+    // benches/turn.rs compares the two
+    fn rotate_face(face: u32) -> u32 {
+        let part4 = face & PIECE4;
+
+        let part05 = (face & (PIECE0 | PIECE5)) << SHIFT2;
+        let part1 = (face & PIECE1) << SHIFT4;
+        let part2 = (face & PIECE2) << SHIFT6;
+
+        let part38 = (face & (PIECE3 | PIECE8)) >> SHIFT2;
+        let part7 = (face & PIECE7) >> SHIFT4;
+        let part6 = (face & PIECE6) >> SHIFT6;
+
+        part4 | part05 | part1 | part2 | part38 | part7 | part6
+    }
+
+    fn rotate_face_(face: u32) -> u32 {
+        let part4 = face & PIECE4;
+
+        let part16 = (face & (PIECE1 | PIECE6)) << SHIFT2;
+        let part3 = (face & PIECE3) << SHIFT4;
+        let part0 = (face & PIECE0) << SHIFT6;
+
+        let part27 = (face & (PIECE2 | PIECE7)) >> SHIFT2;
+        let part5 = (face & PIECE5) >> SHIFT4;
+        let part8 = (face & PIECE8) >> SHIFT6;
+
+        part4 | part16 | part3 | part0 | part27 | part5 | part8
+    }
+
+    fn rotate_face2(face: u32) -> u32 {
+        ((face & PIECE0) << SHIFT8) | ((face & PIECE1) << SHIFT6) | ((face & PIECE2) << SHIFT4) |
+        ((face & PIECE3) << SHIFT2) |
+        ((face & PIECE4)) | ((face & PIECE5) >> SHIFT2) | ((face & PIECE6) >> SHIFT4) |
+        ((face & PIECE7) >> SHIFT6) | ((face & PIECE8) >> SHIFT8)
+    }
+
+
+    fn right(&self) -> Self {
+        Cube {
+            up: (self.up & !MASK258) | (self.front & MASK258),
+            down: (self.down & !MASK258) | (self.back & MASK258),
+            left: self.left,
+            right: Self::rotate_face(self.right),
+            front: (self.front & !MASK258) | (self.down & MASK258),
+            back: (self.back & !MASK258) | (self.up & MASK258),
+        }
+    }
+
+    fn right_(&self) -> Self {
+        Cube {
+            up: (self.up & !MASK258) | (self.back & MASK258),
+            down: (self.down & !MASK258) | (self.front & MASK258),
+            left: self.left,
+            right: Self::rotate_face_(self.right),
+            front: (self.front & !MASK258) | (self.up & MASK258),
+            back: (self.back & !MASK258) | (self.down & MASK258),
+        }
+    }
+
+    fn right2(&self) -> Self {
+        Cube {
+            up: (self.up & !MASK258) | (self.down & MASK258),
+            down: (self.down & !MASK258) | (self.up & MASK258),
+            left: self.left,
+            right: Self::rotate_face2(self.right),
+            front: (self.front & !MASK258) | (self.back & MASK258),
+            back: (self.back & !MASK258) | (self.front & MASK258),
+        }
+    }
+
+
+    fn left(&self) -> Self {
+        Cube {
+            up: (self.up & !MASK036) | (self.back & MASK036),
+            down: (self.down & !MASK036) | (self.front & MASK036),
+            left: Self::rotate_face(self.left),
+            right: self.right,
+            front: (self.front & !MASK036) | (self.up & MASK036),
+            back: (self.back & !MASK036) | (self.down & MASK036),
+        }
+    }
+
+    fn left_(&self) -> Self {
+        Cube {
+            up: (self.up & !MASK036) | (self.front & MASK036),
+            down: (self.down & !MASK036) | (self.back & MASK036),
+            left: Self::rotate_face_(self.left),
+            right: self.right,
+            front: (self.front & !MASK036) | (self.down & MASK036),
+            back: (self.back & !MASK036) | (self.up & MASK036),
+        }
+    }
+
+    fn left2(&self) -> Self {
+        Cube {
+            up: (self.up & !MASK036) | (self.down & MASK036),
+            down: (self.down & !MASK036) | (self.up & MASK036),
+            left: Self::rotate_face2(self.left),
+            right: self.right,
+            front: (self.front & !MASK036) | (self.back & MASK036),
+            back: (self.back & !MASK036) | (self.front & MASK036),
+        }
+    }
+
+
+    fn middle(&self) -> Self {
+        Cube {
+            up: (self.up & !MASK147) | (self.back & MASK147),
+            down: (self.down & !MASK147) | (self.front & MASK147),
+            left: self.left,
+            right: self.right,
+            front: (self.front & !MASK147) | (self.up & MASK147),
+            back: (self.back & !MASK147) | (self.down & MASK147),
+        }
+    }
+
+    fn middle_(&self) -> Self {
+        Cube {
+            up: (self.up & !MASK147) | (self.front & MASK147),
+            down: (self.down & !MASK147) | (self.back & MASK147),
+            left: self.left,
+            right: self.right,
+            front: (self.front & !MASK147) | (self.down & MASK147),
+            back: (self.back & !MASK147) | (self.up & MASK147),
+        }
+    }
+
+    fn middle2(&self) -> Self {
+        Cube {
+            up: (self.up & !MASK147) | (self.down & MASK147),
+            down: (self.down & !MASK147) | (self.up & MASK147),
+            left: self.left,
+            right: self.right,
+            front: (self.front & !MASK147) | (self.back & MASK147),
+            back: (self.back & !MASK147) | (self.front & MASK147),
+        }
+    }
+
+
+    fn standing(&self) -> Self {
+        let right_to_down = ((self.right & PIECE3) << SHIFT2) | (self.right & PIECE4) |
+                            ((self.right & PIECE5) >> SHIFT2);
+
+        let down_to_left = ((self.down & PIECE3) << SHIFT2) | (self.down & PIECE4) |
+                           ((self.down & PIECE5) >> SHIFT2);
+
+        Cube {
+            up: (self.up & !MASK345) | (self.left & MASK345),
+            down: (self.down & !MASK345) | right_to_down,
+            left: (self.left & !MASK345) | down_to_left,
+            right: (self.right & !MASK345) | (self.up & MASK345),
+            front: self.front,
+            back: self.back,
+        }
+    }
+
+    fn standing_(&self) -> Self {
+        let left_to_down = ((self.left & PIECE3) << SHIFT2) | (self.left & PIECE4) |
+                           ((self.left & PIECE5) >> SHIFT2);
+
+        let down_to_right = ((self.down & PIECE3) << SHIFT2) | (self.down & PIECE4) |
+                            ((self.down & PIECE5) >> SHIFT2);
+
+        Cube {
+            up: (self.up & !MASK345) | (self.right & MASK345),
+            down: (self.down & !MASK345) | left_to_down,
+            left: (self.left & !MASK345) | (self.up & MASK345),
+            right: (self.right & !MASK345) | down_to_right,
+            front: self.front,
+            back: self.back,
+        }
+    }
+
+    fn standing2(&self) -> Self {
+        let up_to_down = ((self.up & PIECE3) << SHIFT2) | (self.up & PIECE4) |
+                         ((self.up & PIECE5) >> SHIFT2);
+
+        let down_to_up = ((self.down & PIECE3) << SHIFT2) | (self.down & PIECE4) |
+                         ((self.down & PIECE5) >> SHIFT2);
+
+        Cube {
+            up: (self.up & !MASK345) | down_to_up,
+            down: (self.down & !MASK345) | up_to_down,
+            left: (self.left & !MASK345) | (self.right & MASK345),
+            right: (self.right & !MASK345) | (self.left & MASK345),
+            front: self.front,
+            back: self.back,
+        }
+    }
+
+
+    fn equator(&self) -> Self {
+        let front_to_right = ((self.front & PIECE3) << SHIFT4) | (self.front & PIECE4) |
+                             ((self.front & PIECE5) >> SHIFT4);
+
+        let right_to_back = ((self.right & PIECE1) << SHIFT2) | (self.right & PIECE4) |
+                            ((self.right & PIECE7) >> SHIFT2);
+
+        let back_to_left = ((self.back & PIECE3) << SHIFT4) | (self.back & PIECE4) |
+                           ((self.back & PIECE5) >> SHIFT4);
+
+        let left_to_front = ((self.left & PIECE1) << SHIFT2) | (self.left & PIECE4) |
+                            ((self.left & PIECE7) >> SHIFT2);
+
+        Cube {
+            up: self.up,
+            down: self.down,
+            left: (self.left & !MASK147) | back_to_left,
+            right: (self.right & !MASK147) | front_to_right,
+            front: (self.front & !MASK345) | left_to_front,
+            back: (self.back & !MASK345) | right_to_back,
+        }
+    }
+
+    fn equator_(&self) -> Self {
+        let right_to_front = ((self.right & PIECE1) << SHIFT4) | (self.right & PIECE4) |
+                             ((self.right & PIECE7) >> SHIFT4);
+
+        let back_to_right = ((self.back & PIECE3) >> SHIFT2) | (self.back & PIECE4) |
+                            ((self.back & PIECE5) << SHIFT2);
+
+        let left_to_back = ((self.left & PIECE1) << SHIFT4) | (self.left & PIECE4) |
+                           ((self.left & PIECE7) >> SHIFT4);
+
+        let front_to_left = ((self.front & PIECE3) >> SHIFT2) | (self.front & PIECE4) |
+                            ((self.front & PIECE5) << SHIFT2);
+
+        Cube {
+            up: self.up,
+            down: self.down,
+            left: (self.left & !MASK147) | front_to_left,
+            right: (self.right & !MASK147) | back_to_right,
+            front: (self.front & !MASK345) | right_to_front,
+            back: (self.back & !MASK345) | left_to_back,
+        }
+    }
+
+    fn equator2(&self) -> Self {
+        let front_to_back = ((self.front & PIECE3) << SHIFT2) | (self.front & PIECE4) |
+                            ((self.front & PIECE5) >> SHIFT2);
+
+        let back_to_front = ((self.back & PIECE3) << SHIFT2) | (self.back & PIECE4) |
+                            ((self.back & PIECE5) >> SHIFT2);
+
+        let left_to_right = ((self.left & PIECE1) << SHIFT6) | (self.left & PIECE4) |
+                            ((self.left & PIECE7) >> SHIFT6);
+
+        let right_to_left = ((self.right & PIECE1) << SHIFT6) | (self.right & PIECE4) |
+                            ((self.right & PIECE7) >> SHIFT6);
+
+        Cube {
+            up: self.up,
+            down: self.down,
+            left: (self.left & !MASK147) | right_to_left,
+            right: (self.right & !MASK147) | left_to_right,
+            front: (self.front & !MASK345) | back_to_front,
+            back: (self.back & !MASK345) | front_to_back,
+        }
+    }
+
+
+    fn front(&self) -> Self {
+        let right_to_down = ((self.right & PIECE6) >> SHIFT4) | ((self.right & PIECE7) >> SHIFT6) |
+                            ((self.right & PIECE8) >> SHIFT8);
+
+        let down_to_left = ((self.down & PIECE2) << SHIFT4) | ((self.down & PIECE1) << SHIFT6) |
+                           ((self.down & PIECE0) << SHIFT8);
+
+        Cube {
+            up: (self.up & !MASK678) | (self.left & MASK678),
+            down: (self.down & !MASK012) | right_to_down,
+            left: (self.left & !MASK678) | down_to_left,
+            right: (self.right & !MASK678) | (self.up & MASK678),
+            front: Self::rotate_face(self.front),
+            back: self.back,
+        }
+    }
+
+    fn front_(&self) -> Self {
+        let left_to_down = ((self.left & PIECE6) >> SHIFT4) | ((self.left & PIECE7) >> SHIFT6) |
+                           ((self.left & PIECE8) >> SHIFT8);
+
+        let down_to_right = ((self.down & PIECE2) << SHIFT4) | ((self.down & PIECE1) << SHIFT6) |
+                            ((self.down & PIECE0) << SHIFT8);
+
+        Cube {
+            up: (self.up & !MASK678) | (self.right & MASK678),
+            down: (self.down & !MASK012) | left_to_down,
+            left: (self.left & !MASK678) | (self.up & MASK678),
+            right: (self.right & !MASK678) | down_to_right,
+            front: Self::rotate_face_(self.front),
+            back: self.back,
+        }
+    }
+
+    fn front2(&self) -> Self {
+        let up_to_down = ((self.up & PIECE6) >> SHIFT4) | ((self.up & PIECE7) >> SHIFT6) |
+                         ((self.up & PIECE8) >> SHIFT8);
+
+        let down_to_up = ((self.down & PIECE2) << SHIFT4) | ((self.down & PIECE1) << SHIFT6) |
+                         ((self.down & PIECE0) << SHIFT8);
+
+        Cube {
+            up: (self.up & !MASK678) | down_to_up,
+            down: (self.down & !MASK012) | up_to_down,
+            left: (self.left & !MASK678) | (self.right & MASK678),
+            right: (self.right & !MASK678) | (self.left & MASK678),
+            front: Self::rotate_face2(self.front),
+            back: self.back,
+        }
+    }
+
+
+    fn back(&self) -> Self {
+        let down_to_right = ((self.down & PIECE6) >> SHIFT4) | ((self.down & PIECE7) >> SHIFT6) |
+                            ((self.down & PIECE8) >> SHIFT8);
+
+        let left_to_down = ((self.left & PIECE2) << SHIFT4) | ((self.left & PIECE1) << SHIFT6) |
+                           ((self.left & PIECE0) << SHIFT8);
+
+        Cube {
+            up: (self.up & !MASK012) | (self.right & MASK012),
+            down: (self.down & !MASK678) | left_to_down,
+            left: (self.left & !MASK012) | (self.up & MASK012),
+            right: (self.right & !MASK012) | down_to_right,
+            front: self.front,
+            back: Self::rotate_face(self.back),
+        }
+    }
+
+    fn back_(&self) -> Self {
+        let down_to_left = ((self.down & PIECE6) >> SHIFT4) | ((self.down & PIECE7) >> SHIFT6) |
+                           ((self.down & PIECE8) >> SHIFT8);
+
+        let right_to_down = ((self.right & PIECE2) << SHIFT4) | ((self.right & PIECE1) << SHIFT6) |
+                            ((self.right & PIECE0) << SHIFT8);
+
+        Cube {
+            up: (self.up & !MASK012) | (self.left & MASK012),
+            down: (self.down & !MASK678) | right_to_down,
+            left: (self.left & !MASK012) | down_to_left,
+            right: (self.right & !MASK012) | (self.up & MASK012),
+            front: self.front,
+            back: Self::rotate_face_(self.back),
+        }
+    }
+
+    fn back2(&self) -> Self {
+        let down_to_up = ((self.down & PIECE6) >> SHIFT4) | ((self.down & PIECE7) >> SHIFT6) |
+                         ((self.down & PIECE8) >> SHIFT8);
+
+        let up_to_down = ((self.up & PIECE2) << SHIFT4) | ((self.up & PIECE1) << SHIFT6) |
+                         ((self.up & PIECE0) << SHIFT8);
+
+        Cube {
+            up: (self.up & !MASK012) | down_to_up,
+            down: (self.down & !MASK678) | up_to_down,
+            left: (self.left & !MASK012) | (self.right & MASK012),
+            right: (self.right & !MASK012) | (self.left & MASK012),
+            front: self.front,
+            back: Self::rotate_face2(self.back),
+        }
+    }
+
+
+    fn up(&self) -> Self {
+        let right_to_front = ((self.right & PIECE0) << SHIFT2) | ((self.right & PIECE3) >> SHIFT2) |
+                             ((self.right & PIECE6) >> SHIFT6);
+
+        let front_to_left = ((self.front & PIECE2) << SHIFT6) | ((self.front & PIECE1) << SHIFT4) |
+                            ((self.front & PIECE0) << SHIFT2);
+
+        let left_to_back = ((self.left & PIECE8) >> SHIFT2) | ((self.left & PIECE5) << SHIFT2) |
+                           ((self.left & PIECE2) << SHIFT6);
+
+        let back_to_right = ((self.back & PIECE6) >> SHIFT6) | ((self.back & PIECE7) >> SHIFT4) |
+                            ((self.back & PIECE8) >> SHIFT2);
+
+        Cube {
+            up: Self::rotate_face(self.up),
+            down: self.down,
+            left: (self.left & !MASK258) | front_to_left,
+            right: (self.right & !MASK036) | back_to_right,
+            front: (self.front & !MASK012) | right_to_front,
+            back: (self.back & !MASK678) | left_to_back,
+        }
+    }
+
+    fn up_(&self) -> Self {
+        let right_to_back = ((self.right & PIECE0) << SHIFT6) | ((self.right & PIECE3) << SHIFT4) |
+                            ((self.right & PIECE6) << SHIFT2);
+
+        let front_to_right = ((self.front & PIECE2) >> SHIFT2) | ((self.front & PIECE1) << SHIFT2) |
+                             ((self.front & PIECE0) << SHIFT6);
+
+        let left_to_front = ((self.left & PIECE8) >> SHIFT6) | ((self.left & PIECE5) >> SHIFT4) |
+                            ((self.left & PIECE2) >> SHIFT2);
+
+        let back_to_left = ((self.back & PIECE6) << SHIFT2) | ((self.back & PIECE7) >> SHIFT2) |
+                           ((self.back & PIECE8) >> SHIFT6);
+
+        Cube {
+            up: Self::rotate_face_(self.up),
+            down: self.down,
+            left: (self.left & !MASK258) | back_to_left,
+            right: (self.right & !MASK036) | front_to_right,
+            front: (self.front & !MASK012) | left_to_front,
+            back: (self.back & !MASK678) | right_to_back,
+        }
+    }
+
+    fn up2(&self) -> Self {
+        let right_to_left = ((self.right & PIECE0) << SHIFT8) | ((self.right & PIECE3) << SHIFT2) |
+                            ((self.right & PIECE6) >> SHIFT4);
+
+        let front_to_back = ((self.front & PIECE2) << SHIFT4) | ((self.front & PIECE1) << SHIFT6) |
+                            ((self.front & PIECE0) << SHIFT8);
+
+        let left_to_right = ((self.left & PIECE8) >> SHIFT8) | ((self.left & PIECE5) >> SHIFT2) |
+                            ((self.left & PIECE2) << SHIFT4);
+
+        let back_to_front = ((self.back & PIECE6) >> SHIFT4) | ((self.back & PIECE7) >> SHIFT6) |
+                            ((self.back & PIECE8) >> SHIFT8);
+
+        Cube {
+            up: Self::rotate_face2(self.up),
+            down: self.down,
+            left: (self.left & !MASK258) | right_to_left,
+            right: (self.right & !MASK036) | left_to_right,
+            front: (self.front & !MASK012) | back_to_front,
+            back: (self.back & !MASK678) | front_to_back,
+        }
+    }
+
+
+    fn down(&self) -> Self {
+        let right_to_back = ((self.right & PIECE8) >> SHIFT6) | ((self.right & PIECE5) >> SHIFT4) |
+                            ((self.right & PIECE2) >> SHIFT2);
+
+        let front_to_right = ((self.front & PIECE6) << SHIFT2) | ((self.front & PIECE7) >> SHIFT2) |
+                             ((self.front & PIECE8) >> SHIFT6);
+
+        let left_to_front = ((self.left & PIECE0) << SHIFT6) | ((self.left & PIECE3) << SHIFT4) |
+                            ((self.left & PIECE6) << SHIFT2);
+
+        let back_to_left = ((self.back & PIECE2) >> SHIFT2) | ((self.back & PIECE1) << SHIFT2) |
+                           ((self.back & PIECE0) << SHIFT6);
+
+        Cube {
+            up: self.up,
+            down: Self::rotate_face(self.down),
+            left: (self.left & !MASK036) | back_to_left,
+            right: (self.right & !MASK258) | front_to_right,
+            front: (self.front & !MASK678) | left_to_front,
+            back: (self.back & !MASK012) | right_to_back,
+        }
+    }
+
+    fn down_(&self) -> Self {
+        let back_to_right = ((self.back & PIECE2) << SHIFT6) | ((self.back & PIECE1) << SHIFT4) |
+                            ((self.back & PIECE0) << SHIFT2);
+
+        let right_to_front = ((self.right & PIECE8) >> SHIFT2) | ((self.right & PIECE5) << SHIFT2) |
+                             ((self.right & PIECE2) << SHIFT6);
+
+        let front_to_left = ((self.front & PIECE6) >> SHIFT6) | ((self.front & PIECE7) >> SHIFT4) |
+                            ((self.front & PIECE8) >> SHIFT2);
+
+        let left_to_back = ((self.left & PIECE0) << SHIFT2) | ((self.left & PIECE3) >> SHIFT2) |
+                           ((self.left & PIECE6) >> SHIFT6);
+
+        Cube {
+            up: self.up,
+            down: Self::rotate_face_(self.down),
+            left: (self.left & !MASK036) | front_to_left,
+            right: (self.right & !MASK258) | back_to_right,
+            front: (self.front & !MASK678) | right_to_front,
+            back: (self.back & !MASK012) | left_to_back,
+        }
+    }
+
+    fn down2(&self) -> Self {
+        let left_to_right = ((self.left & PIECE0) << SHIFT8) | ((self.left & PIECE3) << SHIFT2) |
+                            ((self.left & PIECE6) >> SHIFT4);
+
+        let back_to_front = ((self.back & PIECE2) << SHIFT4) | ((self.back & PIECE1) << SHIFT6) |
+                            ((self.back & PIECE0) << SHIFT8);
+
+        let right_to_left = ((self.right & PIECE8) >> SHIFT8) | ((self.right & PIECE5) >> SHIFT2) |
+                            ((self.right & PIECE2) << SHIFT4);
+
+        let front_to_back = ((self.front & PIECE6) >> SHIFT4) | ((self.front & PIECE7) >> SHIFT6) |
+                            ((self.front & PIECE8) >> SHIFT8);
+
+        Cube {
+            up: self.up,
+            down: Self::rotate_face2(self.down),
+            left: (self.left & !MASK036) | right_to_left,
+            right: (self.right & !MASK258) | left_to_right,
+            front: (self.front & !MASK678) | back_to_front,
+            back: (self.back & !MASK012) | front_to_back,
+        }
+    }
+
+
+    // Whole cube rotations, x follows R, y follows U and z follows F
+
+    fn x(&self) -> Self {
+        Cube {
+            up: self.front,
+            down: self.back,
+            left: Self::rotate_face_(self.left),
+            right: Self::rotate_face(self.right),
+            front: self.down,
+            back: self.up,
+        }
+    }
+
+    fn x_(&self) -> Self {
+        Cube {
+            up: self.back,
+            down: self.front,
+            left: Self::rotate_face(self.left),
+            right: Self::rotate_face_(self.right),
+            front: self.up,
+            back: self.down,
+        }
+    }
+
+    fn x2(&self) -> Self {
+        Cube {
+            up: self.down,
+            down: self.up,
+            left: Self::rotate_face2(self.left),
+            right: Self::rotate_face2(self.right),
+            front: self.back,
+            back: self.front,
+        }
+    }
+
+
+    fn y(&self) -> Self {
+        Cube {
+            up: Self::rotate_face(self.up),
+            down: Self::rotate_face_(self.down),
+            left: Self::rotate_face(self.front),
+            right: Self::rotate_face(self.back),
+            front: Self::rotate_face(self.right),
+            back: Self::rotate_face(self.left),
+        }
+    }
+
+    fn y_(&self) -> Self {
+        Cube {
+            up: Self::rotate_face_(self.up),
+            down: Self::rotate_face(self.down),
+            left: Self::rotate_face_(self.back),
+            right: Self::rotate_face_(self.front),
+            front: Self::rotate_face_(self.left),
+            back: Self::rotate_face_(self.right),
+        }
+    }
+
+    fn y2(&self) -> Self {
+        Cube {
+            up: Self::rotate_face2(self.up),
+            down: Self::rotate_face2(self.down),
+            left: Self::rotate_face2(self.right),
+            right: Self::rotate_face2(self.left),
+            front: Self::rotate_face2(self.back),
+            back: Self::rotate_face2(self.front),
+        }
+    }
+
+
+    fn z(&self) -> Self {
+        Cube {
+            up: self.left,
+            down: Self::rotate_face2(self.right),
+            left: Self::rotate_face2(self.down),
+            right: self.up,
+            front: Self::rotate_face(self.front),
+            back: Self::rotate_face_(self.back),
+        }
+    }
+
+    fn z_(&self) -> Self {
+        Cube {
+            up: self.right,
+            down: Self::rotate_face2(self.left),
+            left: self.up,
+            right: Self::rotate_face2(self.down),
+            front: Self::rotate_face_(self.front),
+            back: Self::rotate_face(self.back),
+        }
+    }
+
+    fn z2(&self) -> Self {
+        Cube {
+            up: Self::rotate_face2(self.down),
+            down: Self::rotate_face2(self.up),
+            left: self.right,
+            right: self.left,
+            front: Self::rotate_face2(self.front),
+            back: Self::rotate_face2(self.back),
+        }
+    }
+
+
+    pub fn turn(&self, t: Turn) -> Self {
+        use self::Turn::*;
+
+        match t {
+            U => self.up(),
+            U_ => self.up_(),
+            U2 => self.up2(),
+            D => self.down(),
+            D_ => self.down_(),
+            D2 => self.down2(),
+            L => self.left(),
+            L_ => self.left_(),
+            L2 => self.left2(),
+            R => self.right(),
+            R_ => self.right_(),
+            R2 => self.right2(),
+            F => self.front(),
+            F_ => self.front_(),
+            F2 => self.front2(),
+            B => self.back(),
+            B_ => self.back_(),
+            B2 => self.back2(),
+            M => self.middle(),
+            M_ => self.middle_(),
+            M2 => self.middle2(),
+            S => self.standing(),
+            S_ => self.standing_(),
+            S2 => self.standing2(),
+            E => self.equator(),
+            E_ => self.equator_(),
+            E2 => self.equator2(),
+            X => self.x(),
+            X_ => self.x_(),
+            X2 => self.x2(),
+            Y => self.y(),
+            Y_ => self.y_(),
+            Y2 => self.y2(),
+            Z => self.z(),
+            Z_ => self.z_(),
+            Z2 => self.z2(),
+        }
+    }
+
+    pub fn apply(&self, alg: &[Turn]) -> Self {
+        alg.iter().fold(*self, |cube, &turn| cube.turn(turn))
+    }
+
+    // Applies turns written as for parse_algorithm, like "R U R' U'"
+    pub fn turn_str(&self, s: &str) -> Result<Self, ParseError> {
+        Ok(self.apply(&parse_algorithm(s)?))
+    }
+
+    // The cube before the algorithm and after each of its turns, ending with apply
+    pub fn states(&self, alg: &[Turn]) -> Vec<Cube> {
+        let mut states = Vec::with_capacity(alg.len() + 1);
+        states.push(*self);
+
+        for &turn in alg {
+            let next = states[states.len() - 1].turn(turn);
+            states.push(next);
+        }
+
+        states
+    }
+}
+
+// Three stickers of every face in a row of the net, None leaving a face's place empty
+type NetRow = Vec<Option<[Result<Color, InvalidFace>; 3]>>;
+
+impl Cube {
+    // Back above up, with down, left, up and right beside each other and front below
+    fn net_rows(&self) -> Vec<NetRow> {
+        let stickers = |face, a, b, c| {
+            Some([nth_chunk(a, face), nth_chunk(b, face), nth_chunk(c, face)])
+        };
+
+        let mut rows = Vec::with_capacity(9);
+
+        for &i in &[0, 3, 6] {
+            rows.push(vec![None, None, stickers(self.back, i, i + 1, i + 2)]);
+        }
+
+        // Down is seen from below, so it is flipped
+        for &i in &[0, 3, 6] {
+            rows.push(vec![stickers(self.down, 8 - i, 7 - i, 6 - i),
+                           stickers(self.left, i, i + 1, i + 2),
+                           stickers(self.up, i, i + 1, i + 2),
+                           stickers(self.right, i, i + 1, i + 2)]);
+        }
+
+        for &i in &[0, 3, 6] {
+            rows.push(vec![None, None, stickers(self.front, i, i + 1, i + 2)]);
+        }
+
+        rows
+    }
+
+    fn render_net<F>(&self, blank: &str, sticker: F) -> String
+        where F: Fn(Result<Color, InvalidFace>) -> String
+    {
+        let mut net = String::new();
+
+        for row in self.net_rows() {
+            let faces: Vec<String> = row.iter()
+                .map(|face| match *face {
+                    Some(colors) => colors.iter().map(|&color| sticker(color)).collect(),
+                    None => blank.repeat(3),
+                })
+                .collect();
+
+            net.push_str(&faces.join(" "));
+            net.push('\n');
+        }
+
+        net
+    }
+
+    // The net printed by Display, one letter per sticker and '?' for invalid chunks
+    pub fn to_net_string(&self) -> String {
+        self.render_net(" ", |color| match color {
+            Ok(color) => color.to_string(),
+            Err(_) => "?".to_string(),
+        })
+    }
+
+    // The same net drawn with background colors for terminals supporting 256 colors
+    pub fn to_ansi_net_string(&self) -> String {
+        self.render_net("  ", |color| {
+            let code = match color {
+                Err(_) => return "??".to_string(),
+                Ok(Color::Grey) => 244,
+                Ok(Color::White) => 231,
+                Ok(Color::Yellow) => 226,
+                Ok(Color::Green) => 34,
+                Ok(Color::Blue) => 21,
+                Ok(Color::Red) => 196,
+                Ok(Color::Orange) => 208,
+            };
+
+            format!("\x1b[48;5;{}m  \x1b[0m", code)
+        })
+    }
+}
+
+// Every piece appears exactly once
+fn is_permutation(pieces: &[usize]) -> bool {
+    (0..pieces.len()).all(|piece| pieces.contains(&piece))
+}
+
+// Whether an odd number of swaps gives the permutation
+fn parity(pieces: &[usize]) -> bool {
+    let mut inversions = 0;
+
+    for i in 0..pieces.len() {
+        for j in i + 1..pieces.len() {
+            if pieces[i] > pieces[j] {
+                inversions += 1;
+            }
+        }
+    }
+
+    inversions % 2 == 1
+}
+
+// The color of the nth sticker of a face, in the order of the layout
+pub fn nth_chunk(n: usize, face: u32) -> Result<Color, InvalidFace> {
+    let chunk = ((face >> (n * 3)) & 0b111) as u8;
+
+    Color::from_u8(chunk).ok_or(InvalidFace {
+        face,
+        index: n,
+        chunk,
+    })
+}
+
+// The color of every facelet, in the order of to_facelets:
+// the URFDLB faces one after the other, each in Kociemba's layout
+impl From<[Color; 54]> for Cube {
+    fn from(colors: [Color; 54]) -> Cube {
+        let mut faces = [0; 6];
+
+        for (k, order) in FACELET_ORDER.iter().enumerate() {
+            for (j, &i) in order.iter().enumerate() {
+                faces[k] |= (colors[9 * k + j] as u32) << (3 * i);
+            }
+        }
+
+        Cube {
+            up: faces[0],
+            down: faces[3],
+            left: faces[4],
+            right: faces[1],
+            front: faces[2],
+            back: faces[5],
+        }
+    }
+}
+
+impl From<Cube> for [Color; 54] {
+    fn from(cube: Cube) -> [Color; 54] {
+        let mut colors = [Color::Grey; 54];
+
+        let faces = cube.faces_urfdlb();
+
+        for (k, (&face, order)) in faces.iter().zip(FACELET_ORDER.iter()).enumerate() {
+            for (j, &i) in order.iter().enumerate() {
+                // Chunks that are no color, which only a corrupt cube has, become grey
+                colors[9 * k + j] = nth_chunk(i, face).unwrap_or(Color::Grey);
+            }
+        }
+
+        colors
+    }
+}
+
+impl fmt::Display for Cube {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_net_string())
+    }
+}
+
+// Lower bound on the number of turns needed to reach a pattern from a cube.
+// Must never overestimate, otherwise the search will miss solutions.
+pub type Heuristic = fn(&Cube, &Cube) -> usize;
+
+// A face turn moves at most 20 facelets and a slice turn 12,
+// so each of them can fix at most 20 mismatched facelets
+pub fn facelet_heuristic(cube: &Cube, pattern: &Cube) -> usize {
+    cube.mismatched_facelets(pattern).div_ceil(20)
+}
+
+// Rotations move all but two facelets
+pub fn rotation_heuristic(cube: &Cube, pattern: &Cube) -> usize {
+    cube.mismatched_facelets(pattern).div_ceil(52)
+}
+
+// Whether a cube belongs to some group, like the one with every edge oriented
+pub type Predicate = fn(&Cube) -> bool;
+
+#[derive(Clone, Copy, Debug)]
+pub enum Goal {
+    Pattern(Cube),
+    // Gives the heuristic nothing to go on, so the search is slower than for a pattern
+    Predicate(Predicate),
+}
+
+impl Goal {
+    pub fn matches(&self, cube: &Cube) -> bool {
+        match *self {
+            Goal::Pattern(ref pattern) => cube.matches(pattern),
+            Goal::Predicate(predicate) => predicate(cube),
+        }
+    }
+}
+
+// No edge is flipped, so the cube can be solved without quarter turns of F and B
+pub fn edges_oriented(cube: &Cube) -> bool {
+    cube.cubies().is_some_and(|cubies| cubies.flips.iter().all(|&flip| flip == 0))
+}
+
+// No corner is twisted, so every U and D sticker of a corner is on the U or D face
+pub fn corners_oriented(cube: &Cube) -> bool {
+    cube.cubies().is_some_and(|cubies| cubies.twists.iter().all(|&twist| twist == 0))
+}
+
+// How much a turn adds to the depth of an algorithm.
+// Must be at least one for the heuristics to stay lower bounds.
+pub type Cost = fn(Turn) -> usize;
+
+// The half turn metric, every turn counts as one
+pub fn turn_cost(_: Turn) -> usize {
+    1
+}
+
+// The quarter turn metric, half turns count as two
+pub fn quarter_turn_cost(turn: Turn) -> usize {
+    if turn.is_half_turn() { 2 } else { 1 }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct SearchOptions {
+    // Chosen from the allowed turns if not given
+    pub heuristic: Option<Heuristic>,
+    // Depths are measured with turn_cost if not given
+    pub cost: Option<Cost>,
+    // Skip states already reached at the same or a shallower depth.
+    // Much faster, but algorithms only differing in how they reach some
+    // intermediate state are reported just once.
+    pub transpositions: bool,
+    // Stop once this many algorithms have been found
+    pub max_solutions: Option<usize>,
+    // Stop after searching this depth
+    pub max_depth: Option<usize>,
+    // Start searching at this depth, resuming a search that got through the depths below.
+    // Algorithms shorter than it are not reported again, not even the empty one.
+    pub start_depth: Option<usize>,
+    // Setting this stops the search as soon as possible
+    pub cancel: Option<Arc<AtomicBool>>,
+    // Only report one algorithm for each simplified form
+    pub dedup: bool,
+    // Only report one algorithm for each simplified form up to rotating and
+    // mirroring the cube. Meant for goals with the same symmetries.
+    pub symmetric: bool,
+    // Search on a pool of this many threads instead of rayon's global pool
+    pub threads: Option<usize>,
+    // Hold back the algorithms of each depth until it is done and report them
+    // shortest and then alphabetically first, instead of as they are found
+    pub sorted: bool,
+    // When sorted, order algorithms of the same length by the order of the allowed turns
+    // rather than alphabetically, as a search on one thread would find them. Listing the
+    // preferred turns first brings the algorithms made of them to the front.
+    pub turn_order: bool,
+    // Facelets sharing a group in this layout must end up the same color,
+    // whichever it is. Leave them grey in the pattern. See pack_groups.
+    pub groups: Option<Cube>,
+    // No facelet may end up the color it has in this layout, so a pattern can say what
+    // a facelet must not be as well as what it must be. Grey forbids nothing.
+    pub forbidden: Option<Cube>,
+    // Accept the pattern held in any of the 24 orientations, for goals where it doesn't
+    // matter which way the cube ends up. Wrong for goals tied to positions, and slower
+    // since every orientation is checked. Groups and forbidden colors are not rotated.
+    pub any_orientation: bool,
+    // Must also hold for the cube to match
+    pub predicate: Option<Predicate>,
+    // Read the colors of the pattern relative to its centers, for searches with slice
+    // turns or rotations, which move the centers. See center_recolorings.
+    pub relative_to_centers: bool,
+    // Apply every algorithm to the starting cube again before reporting it, skipping it
+    // with a warning if it misses the goal. Catches mistakes in turn, at some cost.
+    pub debug_verify: bool,
+    // Give up once this passes, sending TimedOut. Checked between depths and every few
+    // thousand nodes, so the search overruns it by a little.
+    pub deadline: Option<Instant>,
+}
+
+// Everything that stays the same throughout a search
+struct SearchContext<'a> {
+    // Reaching any of these is a goal
+    patterns: &'a [Cube],
+    // The goal each pattern is a form of
+    goals: &'a [usize],
+    // Report algorithms as GoalAlgorithm
+    tagged: bool,
+    allowed_turns: &'a [Turn],
+    heuristic: Heuristic,
+    cost: Cost,
+    max_solutions: usize,
+    // Shared between all workers
+    found: &'a AtomicUsize,
+    cancel: Arc<AtomicBool>,
+    // Set once a worker finds the deadline passed, stopping the others. Kept apart from
+    // cancel, which belongs to the caller and may be reused for another search.
+    timed_out: AtomicBool,
+    // Simplified forms of the algorithms reported so far, if deduplicating
+    emitted: Option<&'a Mutex<HashSet<String>>>,
+    // Deduplicate up to these symmetries, if any
+    symmetries: Option<&'a [Vec<Turn>]>,
+    groups: Option<Cube>,
+    forbidden: Option<Cube>,
+    predicate: Option<Predicate>,
+    // The starting cube, if algorithms are verified before being reported
+    verify_from: Option<Cube>,
+    deadline: Option<Instant>,
+    // Report goals costing at least this much instead of only those costing max_depth
+    min_depth: Option<usize>,
+    // The most an algorithm may cost, set before searching each depth
+    max_depth: usize,
+}
+
+// What each worker keeps to itself while searching
+struct Worker<'a> {
+    visited: Option<HashMap<Cube, usize>>,
+    nodes: u64,
+    // The face matches of each pattern for every node being searched from, the deepest
+    // last, so a node only checks again the faces its turn changed
+    matched: Vec<FaceMatches>,
+    // Every worker sends through its own clone of the sender
+    tx: &'a Sender<SearchResult>,
+}
+
+impl<'a> SearchContext<'a> {
+    // The first pattern matched, if any
+    fn goal(&self, cube: &Cube) -> Option<usize> {
+        self.goal_where(cube, |_, pattern| cube.matches(pattern))
+    }
+
+    // The goal of the first pattern for which matched is true, given its index
+    fn goal_where<F>(&self, cube: &Cube, mut matched: F) -> Option<usize>
+        where F: FnMut(usize, &Cube) -> bool
+    {
+        let i = self.patterns.iter().enumerate().position(|(i, pattern)| matched(i, pattern))?;
+
+        if self.groups.is_some_and(|groups| !cube.matches_groups(&groups)) ||
+           self.forbidden.is_some_and(|forbidden| !cube.avoids(&forbidden)) ||
+           self.predicate.is_some_and(|predicate| !predicate(cube)) {
+            return None;
+        }
+
+        Some(self.goals[i])
+    }
+
+    // Only the nearest pattern bounds the distance
+    fn distance(&self, cube: &Cube) -> usize {
+        self.patterns.iter().map(|pattern| (self.heuristic)(cube, pattern)).min().unwrap_or(0)
+    }
+
+    fn result(&self, goal: usize, alg: Algorithm) -> SearchResult {
+        if self.tagged {
+            SearchResult::GoalAlgorithm(goal, alg)
+        } else {
+            SearchResult::Algorithm(alg)
+        }
+    }
+
+    // Algorithms sent so far, as found also counts those over the limit
+    fn reported(&self) -> usize {
+        ::std::cmp::min(self.found.load(Ordering::SeqCst), self.max_solutions)
+    }
+
+    fn should_stop(&self) -> bool {
+        self.cancel.load(Ordering::Relaxed) || self.timed_out.load(Ordering::Relaxed) ||
+        self.found.load(Ordering::Relaxed) >= self.max_solutions
+    }
+
+    fn past_deadline(&self) -> bool {
+        self.deadline.is_some_and(|deadline| Instant::now() >= deadline)
+    }
+}
+
+// Turning the same face twice in a row is never needed, and turns of opposite faces
+// commute so only one of their orders is searched: U before D, L before R, F before B.
+// Turns are declared in that order, and slices and rotations are never reordered.
+fn redundant_after(turn: Turn, last_turn: Turn) -> bool {
+    let outer = |turn: Turn| !turn.is_slice() && !turn.is_rotation();
+
+    turn.same_face(last_turn) ||
+    (turn.axis() == last_turn.axis() && outer(turn) && outer(last_turn) &&
+     (turn as u16) < (last_turn as u16))
+}
+
+// False if the receiver is gone
+fn report(ctx: &SearchContext, goal: usize, alg: &[Turn], tx: &Sender<SearchResult>) -> bool {
+    if let Some(from) = ctx.verify_from {
+        if ctx.goal(&from.apply(alg)) != Some(goal) {
+            // Not on stdout, where the headless mode prints the algorithms
+            eprintln!("Failed to verify {}, skipping it", format_algorithm(alg));
+            return true;
+        }
+    }
+
+    if let Some(emitted) = ctx.emitted {
+        let simplified = simplify(alg);
+
+        let form = match ctx.symmetries {
+            Some(symmetries) => symmetric_form(&simplified, symmetries),
+            None => simplified.canonical_string(),
+        };
+
+        if !emitted.lock().unwrap().insert(form) {
+            return true;
+        }
+    }
+
+    // Claim a slot first so workers finding algorithms at once can't exceed the limit
+    if ctx.found.fetch_add(1, Ordering::SeqCst) >= ctx.max_solutions {
+        return true;
+    }
+
+    tx.send(ctx.result(goal, alg.to_vec().into())).is_ok()
+}
+
+// Turns made so far by a worker are kept on the stack up to this depth,
+// deeper searches are rare enough to spend an allocation on
+const HISTORY_LENGTH: usize = 32;
+
+// Counts the node reached by the turns and reports it if it is a goal.
+// False if there is no need to search on from it.
+fn visit(
+    ctx: &SearchContext,
+    worker: &mut Worker,
+    cube: Cube,
+    cost: usize,
+    turns: &[Turn]
+) -> bool {
+    if ctx.should_stop() {
+        return false;
+    }
+
+    worker.nodes += 1;
+
+    // Reading the clock at every node would slow the search down noticeably
+    if worker.nodes.is_multiple_of(4096) && ctx.past_deadline() {
+        ctx.timed_out.store(true, Ordering::SeqCst);
+        return false;
+    }
+
+    if cost + ctx.distance(&cube) > ctx.max_depth {
+        return false;
+    }
+
+    let reported = match ctx.min_depth {
+        Some(min_depth) => cost >= min_depth,
+        None => cost == ctx.max_depth,
+    };
+
+    // Every turn costs at least one, so nothing below a leaf is within the depth
+    let leaf = cost >= ctx.max_depth;
+
+    let n = ctx.patterns.len();
+    let parent = match turns.last() {
+        Some(&turn) if worker.matched.len() >= n => Some((turn, worker.matched.len() - n)),
+        _ => None,
+    };
+
+    // Nodes searched from keep their matches for their children. Leaves only need to know
+    // whether everything matches, so they stop at the first changed face that doesn't.
+    if !leaf {
+        for (i, pattern) in ctx.patterns.iter().enumerate() {
+            let matched = match parent {
+                Some((turn, base)) => worker.matched[base + i].after(turn, &cube, pattern),
+                None => FaceMatches::new(&cube, pattern),
+            };
+
+            worker.matched.push(matched);
+        }
+    }
+
+    let goal = if !reported {
+        None
+    } else if !leaf {
+        let base = worker.matched.len() - n;
+        ctx.goal_where(&cube, |i, _| worker.matched[base + i].all())
+    } else if let Some((turn, base)) = parent {
+        ctx.goal_where(&cube, |i, pattern| {
+            worker.matched[base + i].all_after(turn, &cube, pattern)
+        })
+    } else {
+        ctx.goal(&cube)
+    };
+
+    let mut search_on = !leaf;
+
+    // Only searching a range goes on past a goal, to the longer algorithms through it
+    if let Some(goal) = goal {
+        if !report(ctx, goal, turns, worker.tx) || ctx.min_depth.is_none() {
+            search_on = false;
+        }
+    }
+
+    if search_on {
+        if let Some(ref mut visited) = worker.visited {
+            if visited.get(&cube).is_some_and(|&seen_cost| seen_cost <= cost) {
+                search_on = false;
+            } else {
+                visited.insert(cube, cost);
+            }
+        }
+    }
+
+    if !search_on && !leaf {
+        let len = worker.matched.len() - n;
+        worker.matched.truncate(len);
+    }
+
+    search_on
+}
+
+// Depth first search from the cube, reporting the goals reached within max_depth
+fn dfs(
+    ctx: &SearchContext,
+    worker: &mut Worker,
+    cube: Cube,
+    last_turn: Turn,
+    // The number of turns made and what they cost
+    depth: usize,
+    cost: usize,
+    history: &mut [Turn]
+) {
+    if !visit(ctx, worker, cube, cost, &history[..depth]) {
+        return;
+    }
+
+    for &turn in ctx.allowed_turns.iter() {
+        if !redundant_after(turn, last_turn) {
+            history[depth] = turn;
+            dfs(ctx,
+                worker,
+                cube.turn(turn),
+                turn,
+                depth + 1,
+                cost + (ctx.cost)(turn),
+                history);
+        }
+    }
+
+    let len = worker.matched.len() - ctx.patterns.len();
+    worker.matched.truncate(len);
+}
+
+pub fn search(cube: Cube, goal: &Goal, allowed_turns: &[Turn], tx: Sender<SearchResult>) {
+    match *goal {
+        Goal::Pattern(ref pattern) => {
+            search_with_options(cube, pattern, allowed_turns, SearchOptions::default(), tx)
+        }
+        Goal::Predicate(predicate) => {
+            let options = SearchOptions { predicate: Some(predicate), ..SearchOptions::default() };
+
+            search_with_options(cube, &Cube::unknown(), allowed_turns, options, tx)
+        }
+    }
+}
+
+// Returns once max_solutions algorithms have been found
+pub fn search_limited(
+    cube: Cube,
+    pattern: &Cube,
+    allowed_turns: &[Turn],
+    max_solutions: usize,
+    tx: Sender<SearchResult>
+) {
+    let options = SearchOptions { max_solutions: Some(max_solutions), ..SearchOptions::default() };
+
+    search_with_options(cube, pattern, allowed_turns, options, tx);
+}
+
+// Returns after searching every depth up to and including max_depth
+pub fn search_bounded(
+    cube: Cube,
+    pattern: &Cube,
+    allowed_turns: &[Turn],
+    max_depth: usize,
+    tx: Sender<SearchResult>
+) {
+    let options = SearchOptions { max_depth: Some(max_depth), ..SearchOptions::default() };
+
+    search_with_options(cube, pattern, allowed_turns, options, tx);
+}
+
+// Iterative broadening: searches to max_depth with just the first width allowed turns,
+// then with twice as many and so on, until a last pass with all of them. Algorithms made of
+// the first turns, so list the common ones first, come before the whole fan-out is searched.
+// The passes reorder discovery, so algorithms no longer arrive shortest first, but the last
+// pass is the exhaustive search and every algorithm is reported once. Everything else the
+// passes send is passed on, except that only the last pass's Exhausted ends the search.
+pub fn search_broadening(
+    cube: Cube,
+    pattern: &Cube,
+    allowed_turns: &[Turn],
+    width: usize,
+    max_depth: usize,
+    tx: Sender<SearchResult>
+) {
+    let mut reported = HashSet::new();
+    let mut width = ::std::cmp::max(width, 1);
+
+    loop {
+        let turns = allowed_turns[..::std::cmp::min(width, allowed_turns.len())].to_vec();
+        let last = width >= allowed_turns.len();
+        let pattern = *pattern;
+        let (pass_tx, pass_rx) = channel();
+        let cancel = Arc::new(AtomicBool::new(false));
+
+        let options = SearchOptions {
+            max_depth: Some(max_depth),
+            cancel: Some(cancel.clone()),
+            ..SearchOptions::default()
+        };
+
+        // Each pass runs on its own thread so its results are passed on as they are found
+        let pass = thread::spawn(move || {
+            search_with_options(cube, &pattern, &turns, options, pass_tx)
+        });
+
+        for res in pass_rx.iter() {
+            let forward = match res {
+                SearchResult::Algorithm(ref alg) => reported.insert(alg.to_string()),
+                SearchResult::Exhausted(_) => last,
+                _ => true,
+            };
+
+            if forward && tx.send(res).is_err() {
+                cancel.store(true, Ordering::SeqCst);
+                break;
+            }
+        }
+
+        // Never more than one pass at a time, even when stopping
+        let _ = pass.join();
+
+        if last || cancel.load(Ordering::SeqCst) {
+            return;
+        }
+
+        width *= 2;
+    }
+}
+
+// Every algorithm up to and including max_depth, shortest first
+pub fn find_algorithms(
+    cube: Cube,
+    pattern: &Cube,
+    allowed_turns: &[Turn],
+    max_depth: usize
+) -> Vec<Algorithm> {
+    let (tx, rx) = channel();
+
+    search_bounded(cube, pattern, allowed_turns, max_depth, tx);
+
+    rx.iter()
+        .filter_map(|res| match res {
+            SearchResult::Algorithm(alg) => Some(alg),
+            _ => None,
+        })
+        .collect()
+}
+
+// Runs find_algorithms on each case of a start and a pattern in turn,
+// so the algorithms for a case are at its index
+pub fn search_cases(
+    cases: &[(Cube, Cube)],
+    allowed_turns: &[Turn],
+    max_depth: usize
+) -> Vec<Vec<Algorithm>> {
+    cases.iter()
+        .map(|&(cube, pattern)| find_algorithms(cube, &pattern, allowed_turns, max_depth))
+        .collect()
+}
+
+pub fn search_with_options(
+    cube: Cube,
+    pattern: &Cube,
+    allowed_turns: &[Turn],
+    options: SearchOptions,
+    tx: Sender<SearchResult>
+) {
+    search_goals(cube, &[*pattern], false, allowed_turns, options, tx);
+}
+
+// Reports algorithms reaching any of the goals as GoalAlgorithm, tagged with the first goal
+// they match. Every goal is checked at every state, so each one slows the search a little.
+pub fn search_any(cube: Cube, goals: &[Cube], allowed_turns: &[Turn], tx: Sender<SearchResult>) {
+    if !goals.is_empty() {
+        search_goals(cube, goals, true, allowed_turns, SearchOptions::default(), tx);
+    }
+}
+
+fn search_goals(
+    cube: Cube,
+    patterns: &[Cube],
+    tagged: bool,
+    allowed_turns: &[Turn],
+    options: SearchOptions,
+    tx: Sender<SearchResult>
+) {
+    let heuristic = options.heuristic.unwrap_or_else(|| {
+        if allowed_turns.iter().any(|turn| turn.is_rotation()) {
+            rotation_heuristic
+        } else {
+            facelet_heuristic
+        }
+    });
+
+    let rotations = rotations();
+    let mut oriented = Vec::new();
+    let mut goals = Vec::new();
+
+    // Every form of the first goal, then of the second and so on
+    for (goal, pattern) in patterns.iter().enumerate() {
+        let forms = if options.any_orientation {
+            rotations.iter().map(|rotation| pattern.apply(rotation)).collect()
+        } else {
+            vec![*pattern]
+        };
+
+        for form in forms {
+            if options.relative_to_centers {
+                oriented.extend(form.center_recolorings());
+            } else {
+                oriented.push(form);
+            }
+
+            goals.resize(oriented.len(), goal);
+        }
+    }
+
+    let found = AtomicUsize::new(0);
+    let emitted = Mutex::new(HashSet::new());
+    let symmetries = if options.symmetric { symmetries() } else { Vec::new() };
+
+    let mut ctx = SearchContext {
+        patterns: &oriented,
+        goals: &goals,
+        tagged,
+        allowed_turns,
+        heuristic,
+        cost: options.cost.unwrap_or(turn_cost),
+        max_solutions: options.max_solutions.unwrap_or(usize::MAX),
+        found: &found,
+        cancel: options.cancel.unwrap_or_else(|| Arc::new(AtomicBool::new(false))),
+        timed_out: AtomicBool::new(false),
+        emitted: if options.dedup || options.symmetric { Some(&emitted) } else { None },
+        symmetries: if options.symmetric { Some(&symmetries) } else { None },
+        groups: options.groups,
+        forbidden: options.forbidden,
+        predicate: options.predicate,
+        verify_from: if options.debug_verify { Some(cube) } else { None },
+        deadline: options.deadline,
+        min_depth: None,
+        max_depth: 0,
+    };
+
+    // Falls back to the global pool if the local one can't be built
+    let pool = options.threads
+        .and_then(|threads| ThreadPoolBuilder::new().num_threads(threads).build().ok());
+
+    let depth_limit = options.max_depth.unwrap_or(usize::MAX);
+    let start_depth = options.start_depth.unwrap_or(0);
+    let transpositions = options.transpositions;
+    let sorted = options.sorted;
+    let turn_order = options.turn_order;
+
+    // Every depth below turns at least once, so a cube that already matches is handled here
+    let goal = if ctx.should_stop() || start_depth > 0 { None } else { ctx.goal(&cube) };
+
+    if let Some(goal) = goal {
+        if let Some(emitted) = ctx.emitted {
+            emitted.lock().unwrap().insert(String::new());
+        }
+
+        ctx.found.fetch_add(1, Ordering::SeqCst);
+
+        for res in [SearchResult::Depth(0), ctx.result(goal, Algorithm::default())] {
+            match tx.send(res) {
+                Ok(()) => {}
+                Err(_) => return,
+            }
+        }
+    }
+
+    let mut max_depth = ::std::cmp::max(start_depth, 1);
+    let mut last_nodes = None;
+
+    while !ctx.should_stop() {
+        if max_depth > depth_limit {
+            let _ = tx.send(SearchResult::Exhausted(depth_limit));
+            return;
+        }
+
+        if ctx.past_deadline() {
+            let _ = tx.send(SearchResult::TimedOut(max_depth));
+            return;
+        }
+
+        match tx.send(SearchResult::Depth(max_depth)) {
+            Ok(()) => {}
+            Err(_) => return,
+        }
+
+        let (depth_tx, depth_rx) = channel();
+        let workers_tx = if sorted { &depth_tx } else { &tx };
+        ctx.max_depth = max_depth;
+        let ctx = &ctx;
+        let start = Instant::now();
+        let reported_before = ctx.reported();
+
+        // The first turns are made here and the workers start from the second, giving them
+        // many small tasks instead of one per turn. That way fewer threads sit idle while
+        // the last tasks of a depth finish.
+        let mut first = Worker { visited: None, nodes: 0, matched: Vec::new(), tx: workers_tx };
+        let mut tasks = Vec::new();
+
+        for &turn in allowed_turns {
+            if visit(ctx, &mut first, cube.turn(turn), (ctx.cost)(turn), &[turn]) {
+                // The workers start again from the second turn
+                first.matched.clear();
+                for &second in allowed_turns {
+                    if !redundant_after(second, turn) {
+                        tasks.push((turn, second));
+                    }
+                }
+            }
+        }
+
+        let senders: Vec<_> = tasks.iter().map(|_| workers_tx.clone()).collect();
+
+        // Each worker counts its own nodes to avoid contention
+        let depth_search = move || {
+            tasks.into_par_iter().zip(senders).map(move |((first, second), sender)| {
+                let mut buffer = [first; HISTORY_LENGTH];
+                let mut spilled;
+
+                let history = if max_depth < HISTORY_LENGTH {
+                    &mut buffer[..]
+                } else {
+                    spilled = vec![first; max_depth + 1];
+                    &mut spilled[..]
+                };
+
+                history[1] = second;
+
+                let cube = cube.turn(first);
+                let cost = (ctx.cost)(first);
+
+                // Each worker keeps its own table to avoid contention,
+                // starting with the state it was split off from
+                let visited = if transpositions {
+                    let mut visited = HashMap::new();
+
+                    if cost < max_depth {
+                        visited.insert(cube, cost);
+                    }
+
+                    Some(visited)
+                } else {
+                    None
+                };
+
+                let mut worker = Worker { visited, nodes: 0, matched: Vec::new(), tx: &sender };
+
+                dfs(ctx,
+                    &mut worker,
+                    cube.turn(second),
+                    second,
+                    2,
+                    cost + (ctx.cost)(second),
+                    history);
+
+                worker.nodes
+            }).sum::<u64>()
+        };
+
+        let nodes = first.nodes + match pool {
+            Some(ref pool) => pool.install(depth_search),
+            None => depth_search(),
+        };
+
+        if ctx.cancel.load(Ordering::SeqCst) {
+            return;
+        }
+
+        // Unlike a cancelled search, one that timed out still sends what it found
+        let timed_out = ctx.timed_out.load(Ordering::SeqCst);
+
+        let mut algs: Vec<(usize, Algorithm)> = depth_rx.try_iter()
+            .filter_map(|res| match res {
+                SearchResult::Algorithm(alg) => Some((0, alg)),
+                SearchResult::GoalAlgorithm(goal, alg) => Some((goal, alg)),
+                _ => None,
+            })
+            .collect();
+
+        if turn_order {
+            algs.sort_by_key(|(_, alg)| {
+                let ranks: Vec<usize> = alg.iter()
+                    .map(|&turn| allowed_turns.iter().position(|&t| t == turn).unwrap())
+                    .collect();
+
+                (alg.len(), ranks)
+            });
+        } else {
+            algs.sort_by_key(|(_, alg)| (alg.len(), alg.to_string()));
+        }
+
+        algs.dedup();
+
+        for (goal, alg) in algs {
+            match tx.send(ctx.result(goal, alg)) {
+                Ok(()) => {}
+                Err(_) => return,
+            }
+        }
+
+        if timed_out {
+            let _ = tx.send(SearchResult::TimedOut(max_depth));
+            return;
+        }
+
+        let elapsed = start.elapsed();
+        let elapsed_ms = elapsed.as_secs() * 1000 + elapsed.subsec_nanos() as u64 / 1_000_000;
+
+        let stats = SearchResult::Stats {
+            depth: max_depth,
+            found: ctx.reported() - reported_before,
+            nodes,
+            elapsed_ms,
+        };
+
+        match tx.send(stats) {
+            Ok(()) => {}
+            Err(_) => return,
+        }
+
+        // The time per node hardly changes between depths, so the time grows like the nodes
+        match last_nodes {
+            Some(last) if last > 0 && max_depth < depth_limit => {
+                let branching = nodes as f64 / last as f64;
+
+                let estimate = SearchResult::Estimate {
+                    depth: max_depth + 1,
+                    branching,
+                    ms: (elapsed_ms as f64 * branching) as u64,
+                };
+
+                match tx.send(estimate) {
+                    Ok(()) => {}
+                    Err(_) => return,
+                }
+            }
+            _ => {}
+        }
+
+        last_nodes = Some(nodes);
+        max_depth += 1;
+    }
+}
+
+// Searches once to max_depth, reporting every algorithm costing from min_depth up to it.
+// Unlike searching depth by depth the shallow states are only expanded once,
+// but algorithms arrive in the order they are found instead of shortest first.
+// No Depth results are sent, only Stats and Exhausted once the whole range is searched,
+// the stats counting every algorithm in the range.
+pub fn search_range(
+    cube: Cube,
+    pattern: &Cube,
+    allowed_turns: &[Turn],
+    min_depth: usize,
+    max_depth: usize,
+    tx: Sender<SearchResult>
+) {
+    let heuristic = if allowed_turns.iter().any(|turn| turn.is_rotation()) {
+        rotation_heuristic
+    } else {
+        facelet_heuristic
+    };
+
+    let found = AtomicUsize::new(0);
+    let patterns = [*pattern];
+
+    let ctx = SearchContext {
+        patterns: &patterns,
+        goals: &[0],
+        tagged: false,
+        allowed_turns,
+        heuristic,
+        cost: turn_cost,
+        max_solutions: usize::MAX,
+        found: &found,
+        cancel: Arc::new(AtomicBool::new(false)),
+        timed_out: AtomicBool::new(false),
+        emitted: None,
+        symmetries: None,
+        groups: None,
+        forbidden: None,
+        predicate: None,
+        verify_from: None,
+        deadline: None,
+        min_depth: Some(min_depth),
+        max_depth,
+    };
+
+    let goal = if min_depth == 0 { ctx.goal(&cube) } else { None };
+
+    if let Some(goal) = goal {
+        if !report(&ctx, goal, &[], &tx) {
+            return;
+        }
+    }
+
+    let senders: Vec<_> = allowed_turns.iter().map(|_| tx.clone()).collect();
+    let ctx = &ctx;
+    let start = Instant::now();
+
+    let nodes: u64 = allowed_turns.into_par_iter().zip(senders).map(move |(&turn, sender)| {
+        let mut buffer = [turn; HISTORY_LENGTH];
+        let mut spilled;
+
+        let history = if max_depth < HISTORY_LENGTH {
+            &mut buffer[..]
+        } else {
+            spilled = vec![turn; max_depth + 1];
+            &mut spilled[..]
+        };
+
+        let mut worker = Worker { visited: None, nodes: 0, matched: Vec::new(), tx: &sender };
+
+        dfs(ctx, &mut worker, cube.turn(turn), turn, 1, (ctx.cost)(turn), history);
+
+        worker.nodes
+    }).sum();
+
+    let elapsed = start.elapsed();
+
+    let stats = SearchResult::Stats {
+        depth: max_depth,
+        found: ctx.reported(),
+        nodes,
+        elapsed_ms: elapsed.as_secs() * 1000 + elapsed.subsec_nanos() as u64 / 1_000_000,
+    };
+
+    for res in [stats, SearchResult::Exhausted(max_depth)] {
+        match tx.send(res) {
+            Ok(()) => {}
+            Err(_) => return,
+        }
+    }
+}
+
+// Grows a frontier by one turn, recording the path to every state not seen before.
+// Searching backwards applies the inverse turns but records the allowed turns themselves.
+// None if stop returned true, which is asked every few thousand states.
+fn expand_frontier(
+    frontier: &[Cube],
+    seen: &mut HashMap<Cube, Vec<Turn>>,
+    allowed_turns: &[Turn],
+    backwards: bool,
+    stop: &dyn Fn() -> bool
+) -> Option<Vec<Cube>> {
+    let mut next_frontier = Vec::new();
+
+    for (i, cube) in frontier.iter().enumerate() {
+        if i.is_multiple_of(4096) && stop() {
+            return None;
+        }
+
+        let path = seen[cube].clone();
+
+        for &turn in allowed_turns {
+            if path.last().is_some_and(|&last| last.same_face(turn)) {
+                continue;
+            }
+
+            let next = cube.turn(if backwards { turn.inverse() } else { turn });
+
+            if let Entry::Vacant(entry) = seen.entry(next) {
+                let mut next_path = path.clone();
+                next_path.push(turn);
+
+                entry.insert(next_path);
+                next_frontier.push(next);
+            }
+        }
+    }
+
+    Some(next_frontier)
+}
+
+// Every state within depth turns of the cube, with the shortest algorithm reaching it.
+// Breadth first, so the nearest states come first, starting with the cube itself.
+// Lazy, each layer is only grown once the one before has been gone through, but every
+// state seen is kept to leave out those reached again.
+pub fn reachable(
+    from: Cube,
+    allowed_turns: &[Turn],
+    depth: usize
+) -> impl Iterator<Item = (Cube, Algorithm)> {
+    let allowed_turns = allowed_turns.to_vec();
+
+    let mut seen = HashMap::new();
+    seen.insert(from, Vec::new());
+
+    let mut layer = vec![from];
+    let mut layer_depth = 0;
+    let mut next = 0;
+
+    ::std::iter::from_fn(move || {
+        while next == layer.len() {
+            if layer_depth == depth || layer.is_empty() {
+                return None;
+            }
+
+            layer = expand_frontier(&layer, &mut seen, &allowed_turns, false, &|| false)?;
+            layer_depth += 1;
+            next = 0;
+        }
+
+        let cube = layer[next];
+        next += 1;
+
+        Some((cube, Algorithm(seen[&cube].clone())))
+    })
+}
+
+// Meet-in-the-middle search growing one frontier from the start and one from the goal.
+// Only works when the goal is fully specified, since the frontiers meet by equality,
+// so goals with grey facelets fall back to the regular search.
+// Only the shortest path to each state is kept, so unlike the regular search
+// not every algorithm is found, only one for each state where the frontiers meet.
+// The frontiers grow with every depth, so give a max_depth or a deadline. Only those and
+// cancel are used from the options, except when falling back.
+pub fn search_bidirectional(
+    from: Cube,
+    to: &Cube,
+    allowed_turns: &[Turn],
+    options: SearchOptions,
+    tx: Sender<SearchResult>
+) {
+    if !to.is_fully_specified() {
+        return search_with_options(from, to, allowed_turns, options, tx);
+    }
+
+    let depth_limit = options.max_depth.unwrap_or(usize::MAX);
+    let cancel = options.cancel.unwrap_or_else(|| Arc::new(AtomicBool::new(false)));
+    let deadline = options.deadline;
+
+    let cancelled = || cancel.load(Ordering::Relaxed);
+    let past_deadline = || deadline.is_some_and(|deadline| Instant::now() >= deadline);
+
+    let mut forward = HashMap::new();
+    let mut backward = HashMap::new();
+
+    forward.insert(from, Vec::new());
+    backward.insert(*to, Vec::new());
+
+    let mut forward_frontier = vec![from];
+    let mut backward_frontier = vec![*to];
+
+    if from == *to {
+        for res in [SearchResult::Depth(0), SearchResult::Algorithm(Algorithm::default())] {
+            match tx.send(res) {
+                Ok(()) => {}
+                Err(_) => return,
+            }
+        }
+    }
+
+    let mut max_depth = 1;
+
+    while max_depth <= depth_limit {
+        match tx.send(SearchResult::Depth(max_depth)) {
+            Ok(()) => {}
+            Err(_) => return,
+        }
+
+        // Alternate between the halves, the forward half being the longer one.
+        // Only the newest layers are joined so every algorithm is found at its own depth.
+        let (frontier, seen, backwards) = if max_depth % 2 == 1 {
+            (&mut forward_frontier, &mut forward, false)
+        } else {
+            (&mut backward_frontier, &mut backward, true)
+        };
+
+        let stop = || cancelled() || past_deadline();
+
+        *frontier = match expand_frontier(frontier, seen, allowed_turns, backwards, &stop) {
+            Some(next) => next,
+            None if cancelled() => return,
+            None => {
+                let _ = tx.send(SearchResult::TimedOut(max_depth));
+                return;
+            }
+        };
+
+        let meeting = if backwards { &backward_frontier } else { &forward_frontier };
+
+        for cube in meeting {
+            let (first, second) = match (forward.get(cube), backward.get(cube)) {
+                (Some(first), Some(second)) => (first, second),
+                _ => continue,
+            };
+
+            if first.len() + second.len() != max_depth {
+                continue;
+            }
+
+            match (first.last(), second.last()) {
+                (Some(&a), Some(&b)) if a.same_face(b) => continue,
+                _ => {}
+            }
+
+            let alg = first.iter().chain(second.iter().rev()).cloned().collect();
+
+            match tx.send(SearchResult::Algorithm(alg)) {
+                Ok(()) => {}
+                Err(_) => return,
+            }
+        }
+
+        max_depth += 1;
+    }
+
+    let _ = tx.send(SearchResult::Exhausted(depth_limit));
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "serde")]
+    extern crate serde_json;
+
+    use super::*;
+    use std::sync::mpsc::Receiver;
+    use std::time::Duration;
+
+    fn random_cube() -> Cube {
+        scramble(25, &mut rand::thread_rng()).0
+    }
+
+    // The algorithms sent by a search, in the order they arrived
+    fn algorithms(
+        cube: Cube,
+        pattern: &Cube,
+        allowed_turns: &[Turn],
+        options: SearchOptions
+    ) -> Vec<Algorithm> {
+        let (tx, rx) = channel();
+
+        search_with_options(cube, pattern, allowed_turns, options, tx);
+
+        sent_algorithms(rx)
+    }
+
+    fn sent_algorithms(rx: Receiver<SearchResult>) -> Vec<Algorithm> {
+        rx.iter()
+            .filter_map(|res| match res {
+                SearchResult::Algorithm(alg) => Some(alg),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn sorted_strings(algs: &[Algorithm]) -> Vec<String> {
+        let mut strings: Vec<String> = algs.iter().map(|alg| alg.to_string()).collect();
+        strings.sort();
+        strings
+    }
+
+    // Swaps the UR and UF edges, giving a cube that no turns reach from the original
+    fn swap_edges(cube: Cube) -> Cube {
+        let mut colors: [Color; 54] = cube.into();
+        let (ur, uf) = (EDGE_FACELETS[0], EDGE_FACELETS[1]);
+        colors.swap(ur[0], uf[0]);
+        colors.swap(ur[1], uf[1]);
+        Cube::from(colors)
+    }
+
+    fn sorted_colors(cube: &Cube) -> Vec<u8> {
+        let colors: [Color; 54] = (*cube).into();
+        let mut sorted: Vec<u8> = colors.iter().map(|&color| color as u8).collect();
+        sorted.sort();
+        sorted
+    }
+
+    #[test]
+    fn turns_are_bijections() {
+        for &turn in ALL_TURNS.iter() {
+            let mut targets = HashSet::new();
+
+            for i in 0..54 {
+                // A cube with a single sticker marked must keep it, and no other
+                // sticker may end up where it goes
+                let mut colors = [Color::Grey; 54];
+                colors[i] = Color::White;
+
+                let turned: [Color; 54] = Cube::from(colors).turn(turn).into();
+                let marked: Vec<usize> =
+                    (0..54).filter(|&j| turned[j] == Color::White).collect();
+
+                assert_eq!(marked.len(), 1, "{} loses or copies sticker {}", turn, i);
+                assert!(targets.insert(marked[0]), "{} moves two stickers to one", turn);
+            }
+        }
+    }
+
+    #[test]
+    fn turns_keep_the_stickers() {
+        for _ in 0..20 {
+            let cube = random_cube();
+
+            for &turn in ALL_TURNS.iter() {
+                assert_eq!(sorted_colors(&cube.turn(turn)), sorted_colors(&cube), "{}", turn);
+            }
+        }
+    }
+
+    #[test]
+    fn turns_have_order_four() {
+        for _ in 0..20 {
+            let cube = random_cube();
+
+            for &turn in ALL_TURNS.iter() {
+                let order = if turn.is_half_turn() { 2 } else { 4 };
+
+                let mut turned = cube;
+
+                for i in 1..order + 1 {
+                    turned = turned.turn(turn);
+                    assert_eq!(turned == cube, i == order, "{} turned {} times", turn, i);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn one_thread_finds_the_same_algorithms() {
+        let from = Cube::solved_state().turn_str("R U R' U R U2 R'").unwrap();
+        let turns = [Turn::R, Turn::R_, Turn::R2, Turn::U, Turn::U_, Turn::U2];
+
+        let bounded = SearchOptions { max_depth: Some(8), ..SearchOptions::default() };
+        let one = SearchOptions { threads: Some(1), ..bounded.clone() };
+
+        let expected = algorithms(from, &Cube::solved_state(), &turns, bounded);
+        let found = algorithms(from, &Cube::solved_state(), &turns, one);
+
+        assert!(!expected.is_empty());
+        assert_eq!(sorted_strings(&found), sorted_strings(&expected));
+    }
+
+    #[test]
+    fn heuristics_reach_zero_exactly_when_matching() {
+        let mut rng = rand::thread_rng();
+
+        for _ in 0..2000 {
+            let cube = random_cube();
+
+            // Patterns made from the cube itself or another one, with some facelets grey
+            let base = if rng.gen() { cube } else { random_cube() };
+            let mut colors: [Color; 54] = base.into();
+
+            for _ in 0..rng.gen_range(0, 54) {
+                colors[rng.gen_range(0, 54)] = Color::Grey;
+            }
+
+            let pattern = Cube::from(colors);
+            let matches = cube.matches(&pattern);
+
+            assert_eq!(facelet_heuristic(&cube, &pattern) == 0, matches);
+            assert_eq!(rotation_heuristic(&cube, &pattern) == 0, matches);
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn cubes_round_trip_through_json() {
+        let json = serde_json::to_string(&Cube::solved_state()).unwrap();
+        let cube: Cube = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(cube, Cube::solved_state());
+    }
+
+    #[test]
+    fn deadline_stops_search_without_cancelling() {
+        let (cube, _) = scramble(25, &mut rand::thread_rng());
+        let cancel = Arc::new(AtomicBool::new(false));
+
+        let options = SearchOptions {
+            cancel: Some(cancel.clone()),
+            deadline: Some(Instant::now() + Duration::from_millis(10)),
+            ..SearchOptions::default()
+        };
+
+        let (tx, rx) = channel();
+        let start = Instant::now();
+
+        search_with_options(cube, &Cube::solved_state(), &ALL_TURNS[..18], options, tx);
+
+        assert!(start.elapsed() < Duration::from_secs(2));
+        assert!(rx.iter().any(|res| matches!(res, SearchResult::TimedOut(_))));
+        assert!(!cancel.load(Ordering::SeqCst));
+    }
+
+    // The algorithms a search for the goal sends before starting on depth two
+    fn one_turn_algorithms(cube: Cube, goal: Goal) -> Vec<String> {
+        let (tx, rx) = channel();
+        let worker = thread::spawn(move || search(cube, &goal, &ALL_TURNS[..18], tx));
+
+        let mut algs = Vec::new();
+
+        for res in rx.iter() {
+            match res {
+                SearchResult::Algorithm(alg) => algs.push(alg.to_string()),
+                SearchResult::Depth(2) => break,
+                _ => {}
+            }
+        }
+
+        // The search stops once it can't send
+        drop(rx);
+        worker.join().unwrap();
+
+        algs.sort();
+        algs
+    }
+
+    #[test]
+    fn predicate_goals_find_known_algorithms() {
+        // A quarter turn of F flips four edges, undone by turning F again either way
+        let flipped = Cube::solved_state().turn(Turn::F);
+        assert_eq!(one_turn_algorithms(flipped, Goal::Predicate(edges_oriented)),
+                   vec!["F", "F'"]);
+
+        // And one of R twists four corners
+        let twisted = Cube::solved_state().turn(Turn::R);
+        assert_eq!(one_turn_algorithms(twisted, Goal::Predicate(corners_oriented)),
+                   vec!["R", "R'"]);
+    }
+
+    #[test]
+    fn verification_drops_wrong_algorithms() {
+        let from = Cube::solved_state().turn(Turn::R);
+        let patterns = [Cube::solved_state()];
+        let found = AtomicUsize::new(0);
+
+        let ctx = SearchContext {
+            patterns: &patterns,
+            goals: &[0],
+            tagged: false,
+            allowed_turns: &ALL_TURNS[..18],
+            heuristic: facelet_heuristic,
+            cost: turn_cost,
+            max_solutions: usize::MAX,
+            found: &found,
+            cancel: Arc::new(AtomicBool::new(false)),
+            timed_out: AtomicBool::new(false),
+            emitted: None,
+            symmetries: None,
+            groups: None,
+            forbidden: None,
+            predicate: None,
+            verify_from: Some(from),
+            deadline: None,
+            min_depth: None,
+            max_depth: 1,
+        };
+
+        let (tx, rx) = channel();
+
+        // R' undoes R, but not with its direction corrupted
+        assert!(report(&ctx, 0, &[Turn::R_], &tx));
+        assert!(report(&ctx, 0, &[Turn::R], &tx));
+        drop(tx);
+
+        let sent: Vec<String> = rx.iter()
+            .filter_map(|res| match res {
+                SearchResult::Algorithm(alg) => Some(alg.to_string()),
+                _ => None,
+            })
+            .collect();
+
+        assert_eq!(sent, vec!["R'"]);
+        assert_eq!(found.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn triggers_are_found_from_the_left() {
+        let triggers = |s: &str| find_triggers(&parse_algorithm(s).unwrap());
+
+        // A sexy move followed by a sledgehammer
+        assert_eq!(triggers("R U R' U' R' F R F'"), vec![(0, 0), (4, 2)]);
+        // R U R' starts a sexy move as well, which wins as the longer trigger
+        assert_eq!(triggers("F R U R' U' F'"), vec![(1, 0)]);
+        // The sexy move starting at R' U' overlaps the first, so only that one counts
+        assert_eq!(triggers("R U R' U' R U"), vec![(0, 0)]);
+        assert_eq!(triggers("R U R' U R U2 R'"), vec![(0, 4)]);
+        assert_eq!(triggers("U2 L' U L"), vec![]);
+    }
+
+    // The heuristic never claims more turns are needed than the shortest algorithm takes,
+    // for every state the turns reach within the depth
+    fn assert_admissible(heuristic: Heuristic, allowed_turns: &[Turn], depth: usize) {
+        let solved = Cube::solved_state();
+
+        for (cube, alg) in reachable(solved, allowed_turns, depth) {
+            assert!(heuristic(&cube, &solved) <= alg.len(), "{}", alg);
+        }
+    }
+
+    #[test]
+    fn heuristics_are_admissible() {
+        assert_admissible(facelet_heuristic, &ALL_TURNS[..18], 4);
+        assert_admissible(facelet_heuristic, &ALL_TURNS[..27], 3);
+        assert_admissible(rotation_heuristic, &ALL_TURNS, 3);
+    }
+
+    #[test]
+    fn commutators_and_conjugates_are_recognized() {
+        let form = |s: &str| commutator_form(&parse_algorithm(s).unwrap()).map(|f| f.to_string());
+
+        assert_eq!(form("R U R' D R U' R' D'"), Some("[R U R', D]".to_string()));
+        assert_eq!(form("R U R' U'"), Some("[R, U]".to_string()));
+        assert_eq!(form("F R U R' U' F'"), Some("[F: R U R' U']".to_string()));
+        assert_eq!(form("R U R' U R U2 R'"), Some("[R: U R' U R U2]".to_string()));
+        assert_eq!(form("R U2 D'"), None);
+    }
+
+    #[test]
+    fn reachable_lists_each_state_once() {
+        let u_turns = [Turn::U, Turn::U_, Turn::U2];
+        let states: Vec<(Cube, Algorithm)> =
+            reachable(Cube::solved_state(), &u_turns, 1).collect();
+
+        assert_eq!(states.len(), 4);
+        assert_eq!(states[0], (Cube::solved_state(), Algorithm::default()));
+
+        for &(cube, ref alg) in &states[1..] {
+            assert_eq!(alg.len(), 1);
+            assert_eq!(Cube::solved_state().apply(alg), cube);
+        }
+
+        // Turning U again only reaches the same four states
+        assert_eq!(reachable(Cube::solved_state(), &u_turns, 3).count(), 4);
+
+        // Only the layers gone through are grown, so the first states of a depth far too
+        // deep to list come right away
+        let first: Vec<(Cube, Algorithm)> =
+            reachable(Cube::solved_state(), &ALL_TURNS, 20).take(100).collect();
+
+        assert_eq!(first.len(), 100);
+        assert_eq!(first[99].1.len(), 2);
+    }
+
+    #[test]
+    fn net_string_is_unchanged() {
+        // As printed by Display before it went through to_net_string
+        let solved = concat!("        BBB\n",
+                             "        BBB\n",
+                             "        BBB\n",
+                             "WWW RRR YYY OOO\n",
+                             "WWW RRR YYY OOO\n",
+                             "WWW RRR YYY OOO\n",
+                             "        GGG\n",
+                             "        GGG\n",
+                             "        GGG\n");
+        let scrambled = concat!("        WYR\n",
+                                "        OBR\n",
+                                "        OOO\n",
+                                "BOG RBB YGG WYY\n",
+                                "RWW BRR WYY BOG\n",
+                                "YYY ORR WOO BWR\n",
+                                "        BWW\n",
+                                "        BGG\n",
+                                "        GGG\n");
+
+        assert_eq!(Cube::solved_state().to_net_string(), solved);
+        assert_eq!(Cube::solved_state().turn_str("R U F' L2 D B'").unwrap().to_net_string(),
+                   scrambled);
+    }
+
+    #[test]
+    fn scramble_never_turns_an_axis_twice_in_a_row() {
+        let mut rng = rand::thread_rng();
+
+        for _ in 0..100 {
+            let (cube, alg) = scramble(25, &mut rng);
+            assert_eq!(alg.len(), 25);
+            assert_eq!(Cube::solved_state().apply(&alg), cube);
+
+            for pair in alg.windows(2) {
+                assert_ne!(pair[0].axis(), pair[1].axis(), "{}", alg);
+            }
+        }
+    }
+
+    #[test]
+    fn rotations_compose_like_the_cube_does() {
+        let cube = random_cube();
+        let same = |a: &str, b: &str| cube.turn_str(a).unwrap() == cube.turn_str(b).unwrap();
+
+        for rotation in &["x", "x'", "x2", "y", "y'", "y2", "z", "z'", "z2"] {
+            assert_eq!(cube.turn_str(&[*rotation; 4].join(" ")).unwrap(), cube);
+            assert!(!same(rotation, ""));
+        }
+
+        assert!(same("y y y y", ""));
+        assert!(same("y y'", ""));
+        assert!(same("y y", "y2"));
+
+        // Rotations about different axes don't commute, but conjugate each other
+        assert!(!same("x y x' y'", ""));
+        assert!(same("x y x' y' y x y' x'", ""));
+        assert!(same("x y x'", "z"));
+        assert!(same("y x y'", "z'"));
+        assert!(same("x2 y2", "z2"));
+        assert!(same("x y", "y z"));
+
+        // And each turns the whole cube like the layers beside its axis together
+        assert!(same("x", "R M' L'"));
+        assert!(same("y", "U E' D'"));
+        assert!(same("z", "F S B'"));
+    }
+
+    #[test]
+    fn simplify_keeps_the_cube_state() {
+        use self::Turn::*;
+
+        let mut rng = rand::thread_rng();
+        // Few faces, so that turns merge and cancel often
+        let turns = [U, U_, U2, D, D_, R, R2, M, X, Y_];
+
+        for _ in 0..1000 {
+            let len = rng.gen_range(0, 30);
+            let alg: Vec<Turn> = (0..len).map(|_| *rng.choose(&turns).unwrap()).collect();
+            let simplified = simplify(&alg);
+
+            let cube = random_cube();
+            assert_eq!(cube.apply(&simplified), cube.apply(&alg), "{:?}", alg);
+            assert!(simplified.len() <= alg.len());
+            assert!(simplified.windows(2).all(|pair| !pair[0].same_face(pair[1])),
+                    "{:?}", simplified);
+        }
+    }
+
+    #[test]
+    fn swapping_two_edges_is_unsolvable() {
+        let cube = random_cube();
+        assert!(cube.is_solvable());
+
+        let swapped = swap_edges(cube);
+        assert!(swapped.cubies().is_some());
+        assert!(!swapped.is_solvable());
+
+        // Also after turning, which never changes whether a cube can be solved
+        for &turn in ALL_TURNS.iter() {
+            assert!(!swapped.turn(turn).is_solvable());
+        }
+    }
+
+    #[test]
+    fn bounded_search_for_an_unreachable_pattern_is_exhausted() {
+        let pattern = swap_edges(Cube::solved_state());
+
+        let (tx, rx) = channel();
+        search_bounded(Cube::solved_state(), &pattern, &ALL_TURNS[..18], 3, tx);
+
+        let results: Vec<SearchResult> = rx.iter().collect();
+
+        assert!(!results.iter().any(|res| matches!(res, SearchResult::Algorithm(_))));
+        assert!(!results.iter().any(|res| matches!(res, SearchResult::Depth(4))));
+        assert!(matches!(results.last(), Some(SearchResult::Exhausted(3))));
+    }
+
+    #[test]
+    fn cancelling_ends_the_search_thread() {
+        let cancel = Arc::new(AtomicBool::new(false));
+        let options = SearchOptions { cancel: Some(cancel.clone()), ..SearchOptions::default() };
+
+        // Never found, so only the flag can end the search
+        let pattern = swap_edges(Cube::solved_state());
+
+        let (tx, rx) = channel();
+        let worker = thread::spawn(move || {
+            search_with_options(random_cube(), &pattern, &ALL_TURNS[..18], options, tx)
+        });
+
+        // Well into the search, with the receiver still around
+        while !matches!(rx.recv().unwrap(), SearchResult::Depth(7)) {}
+
+        let start = Instant::now();
+        cancel.store(true, Ordering::SeqCst);
+        worker.join().unwrap();
+
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert!(rx.iter().all(|res| !matches!(res, SearchResult::Depth(_))));
+    }
+
+    // The nodes a search visited at each depth, with the algorithms it found
+    fn nodes_per_depth(
+        cube: Cube,
+        pattern: &Cube,
+        allowed_turns: &[Turn],
+        options: SearchOptions
+    ) -> (Vec<u64>, Vec<Algorithm>) {
+        let (tx, rx) = channel();
+
+        search_with_options(cube, pattern, allowed_turns, options, tx);
+
+        let mut nodes = Vec::new();
+        let mut algs = Vec::new();
+
+        for res in rx.iter() {
+            match res {
+                SearchResult::Stats { nodes: n, .. } => nodes.push(n),
+                SearchResult::Algorithm(alg) => algs.push(alg),
+                _ => {}
+            }
+        }
+
+        (nodes, algs)
+    }
+
+    #[test]
+    fn transpositions_visit_fewer_nodes() {
+        use self::Turn::*;
+
+        // Slice turns commute with the faces beside them but are searched in either order,
+        // so the same states come up again and again
+        let turns = [R, R_, R2, L, L_, U, U_, U2, M, M_, M2];
+        let scramble = [R, U, M_, U2, L, M, U_];
+        let cube = Cube::solved_state().apply(&scramble);
+        let solved = Cube::solved_state();
+        let options = || SearchOptions { max_solutions: Some(1), ..SearchOptions::default() };
+
+        let (plain_nodes, plain) = nodes_per_depth(cube, &solved, &turns, options());
+        let (nodes, algs) = nodes_per_depth(cube,
+                                            &solved,
+                                            &turns,
+                                            SearchOptions { transpositions: true, ..options() });
+
+        assert_eq!(algs.len(), 1);
+        assert_eq!(algs[0].len(), plain[0].len());
+        assert_eq!(nodes.len(), plain_nodes.len());
+        assert!(nodes.iter().zip(&plain_nodes).all(|(nodes, plain)| nodes <= plain));
+        assert!(nodes.last() < plain_nodes.last());
+    }
+
+    #[test]
+    fn opposite_faces_are_turned_in_one_order() {
+        fn no_estimate(_: &Cube, _: &Cube) -> usize {
+            0
+        }
+
+        // Never found, so every depth is searched in full
+        let pattern = swap_edges(Cube::solved_state());
+        let options = SearchOptions {
+            heuristic: Some(no_estimate),
+            max_depth: Some(4),
+            ..SearchOptions::default()
+        };
+
+        let (nodes, _) = nodes_per_depth(Cube::solved_state(), &pattern, &ALL_TURNS[..18], options);
+
+        // Sequences of each length with neither the same face twice in a row nor opposite
+        // faces out of order number 18, 243, 3240 and 43254. With just the first rule they
+        // would be 18, 270, 4050 and 60750.
+        let counts = [18, 243, 3240, 43254];
+        let expected: Vec<u64> = (1..5).map(|len| counts[..len].iter().sum()).collect();
+
+        assert_eq!(nodes, expected);
+    }
+
+    #[test]
+    fn matching_cube_gives_the_empty_algorithm() {
+        let cube = random_cube();
+        let (tx, rx) = channel();
+
+        search_limited(cube, &cube, &ALL_TURNS[..18], 1, tx);
+
+        let results: Vec<SearchResult> = rx.iter().collect();
+
+        assert!(matches!(results[0], SearchResult::Depth(0)));
+        assert!(matches!(results[1], SearchResult::Algorithm(ref alg) if alg.is_empty()));
+        assert_eq!(results.iter().filter(|res| matches!(res, SearchResult::Algorithm(_))).count(),
+                   1);
+    }
+
+    #[test]
+    fn conjugates_by_a_rotation_are_symmetric() {
+        use self::Turn::*;
+
+        let symmetries = symmetries();
+
+        // F is R seen from the other side after a y rotation, as are longer algorithms
+        assert_eq!(symmetric_form(&[R], &symmetries), symmetric_form(&[F], &symmetries));
+        assert_eq!(symmetric_form(&[R, U, R_], &symmetries),
+                   symmetric_form(&[F, U, F_], &symmetries));
+        assert_ne!(symmetric_form(&[R, U], &symmetries), symmetric_form(&[R, U2], &symmetries));
+
+        // Any cube matches the unknown pattern, which every symmetry keeps
+        let options = |symmetric| SearchOptions {
+            start_depth: Some(1),
+            max_depth: Some(1),
+            symmetric,
+            ..SearchOptions::default()
+        };
+
+        let plain = algorithms(Cube::solved_state(), &Cube::unknown(), &[R, F], options(false));
+        assert_eq!(sorted_strings(&plain), vec!["F", "R"]);
+
+        let symmetric = algorithms(Cube::solved_state(), &Cube::unknown(), &[R, F], options(true));
+        assert_eq!(symmetric.len(), 1);
+    }
+
+    #[test]
+    fn search_any_tells_which_goal_was_reached() {
+        use self::Turn::*;
+
+        let cube = random_cube();
+        let goals = [cube.apply(&[R, U]), cube.apply(&[F2, D_])];
+        let (tx, rx) = channel();
+        let worker = thread::spawn(move || search_any(cube, &goals, &ALL_TURNS[..18], tx));
+
+        let mut found = Vec::new();
+
+        for res in rx.iter() {
+            match res {
+                SearchResult::GoalAlgorithm(goal, alg) => found.push((goal, alg.to_string())),
+                SearchResult::Algorithm(alg) => panic!("{} sent without its goal", alg),
+                SearchResult::Depth(3) => break,
+                _ => {}
+            }
+        }
+
+        drop(rx);
+        worker.join().unwrap();
+
+        found.sort();
+        assert_eq!(found, vec![(0, "R U".to_string()), (1, "F2 D'".to_string())]);
+    }
+
+    #[test]
+    fn forbidden_colors_exclude_cubes() {
+        // White may be anywhere but on U1, the first facelet
+        let mut colors = [Color::Grey; 54];
+        colors[0] = Color::White;
+        let forbidden = Cube::from(colors);
+
+        for _ in 0..1000 {
+            let cube = random_cube();
+            let facelets: [Color; 54] = cube.into();
+
+            assert_eq!(cube.avoids(&forbidden), facelets[0] != Color::White);
+        }
+
+        let options = SearchOptions {
+            start_depth: Some(1),
+            max_depth: Some(1),
+            forbidden: Some(forbidden),
+            ..SearchOptions::default()
+        };
+        let algs = algorithms(Cube::solved_state(), &Cube::unknown(), &ALL_TURNS[..18], options);
+
+        // Only L2 and B2 bring a white facelet of the down face up to U1
+        assert_eq!(algs.len(), 16);
+
+        for alg in &algs {
+            let facelets: [Color; 54] = Cube::solved_state().apply(alg).into();
+            assert_ne!(facelets[0], Color::White, "{}", alg);
+        }
+    }
+
+    #[test]
+    fn stats_count_the_algorithms_of_their_depth() {
+        // Every cube matches the unknown pattern, so every sequence of turns is found
+        let options = SearchOptions { max_depth: Some(3), ..SearchOptions::default() };
+        let (tx, rx) = channel();
+
+        search_with_options(random_cube(), &Cube::unknown(), &ALL_TURNS[..18], options, tx);
+
+        let mut sent = 0;
+        let mut found_per_depth = Vec::new();
+
+        for res in rx.iter() {
+            match res {
+                SearchResult::Depth(_) => sent = 0,
+                SearchResult::Algorithm(_) => sent += 1,
+                SearchResult::Stats { depth, found, .. } => {
+                    assert_eq!(found, sent, "depth {}", depth);
+                    found_per_depth.push(found);
+                }
+                _ => {}
+            }
+        }
+
+        // The empty algorithm comes before any stats, then the canonical sequences of each length
+        assert_eq!(found_per_depth, vec![18, 243, 3240]);
+    }
+
+    #[test]
+    fn turn_order_changes_the_order_but_not_the_algorithms() {
+        use self::Turn::*;
+
+        let options = || SearchOptions {
+            start_depth: Some(2),
+            max_depth: Some(2),
+            sorted: true,
+            turn_order: true,
+            ..SearchOptions::default()
+        };
+
+        let turns = [R, R_, U, U_];
+        let reversed = [U_, U, R_, R];
+        let forward = algorithms(Cube::solved_state(), &Cube::unknown(), &turns, options());
+        let backward = algorithms(Cube::solved_state(), &Cube::unknown(), &reversed, options());
+
+        let strings = |algs: &[Algorithm]| -> Vec<String> {
+            algs.iter().map(|alg| alg.to_string()).collect()
+        };
+
+        assert_eq!(strings(&forward)[..3], ["R U", "R U'", "R' U"]);
+        assert_eq!(strings(&backward)[..3], ["U' R'", "U' R", "U R'"]);
+        assert_eq!(sorted_strings(&forward), sorted_strings(&backward));
+    }
+
+    #[test]
+    fn estimates_are_close_to_the_next_depth() {
+        let cube = Cube::solved_state().turn_str("R U F' L2 D B' R2").unwrap();
+        let options = SearchOptions { max_depth: Some(6), ..SearchOptions::default() };
+        let (tx, rx) = channel();
+
+        search_with_options(cube, &Cube::solved_state(), &ALL_TURNS[..18], options, tx);
+
+        let mut nodes = HashMap::new();
+        let mut estimates = Vec::new();
+
+        for res in rx.iter() {
+            match res {
+                SearchResult::Stats { depth, nodes: n, .. } => {
+                    nodes.insert(depth, n as f64);
+                }
+                SearchResult::Estimate { depth, branching, .. } => {
+                    estimates.push((depth, branching));
+                }
+                _ => {}
+            }
+        }
+
+        // None for the first depth, which has nothing to go by, nor after the last
+        assert_eq!(estimates.iter().map(|&(depth, _)| depth).collect::<Vec<_>>(), vec![3, 4, 5, 6]);
+
+        // Until the facelet heuristic lets more than the first turns through, there is
+        // too little to go by
+        for (depth, branching) in estimates.into_iter().skip(1) {
+            let estimated = nodes[&(depth - 1)] * branching;
+            let actual = nodes[&depth];
+
+            assert!(estimated / 2.0 < actual && actual < estimated * 2.0,
+                    "depth {}: estimated {} nodes, searched {}", depth, estimated, actual);
+        }
+    }
+
+    #[test]
+    fn starting_deeper_skips_shorter_algorithms() {
+        use self::Turn::*;
+
+        let options = || SearchOptions {
+            start_depth: Some(3),
+            max_depth: Some(4),
+            ..SearchOptions::default()
+        };
+
+        // Any algorithm matches, but only those of three and four turns are sent
+        let algs = algorithms(random_cube(), &Cube::unknown(), &[R, R_, U, U_], options());
+        assert!(algs.iter().all(|alg| alg.len() >= 3));
+        assert!(algs.iter().any(|alg| alg.len() == 3));
+
+        // And a two turn solution is not sent, nor the empty one before it
+        let cube = Cube::solved_state().apply(&[R, U]);
+        let algs = algorithms(cube, &Cube::solved_state(), &ALL_TURNS[..18], options());
+        assert!(algs.iter().all(|alg| alg.len() >= 3));
+        assert!(!algs.iter().any(|alg| alg.to_string() == "U' R'"));
+    }
+
+    #[test]
+    fn broadening_finds_what_the_plain_search_finds() {
+        use self::Turn::*;
+
+        // Only the up face has to be solved, which turning U or D afterwards keeps
+        let mut colors: [Color; 54] = Cube::solved_state().into();
+
+        for color in colors[9..].iter_mut() {
+            *color = Color::Grey;
+        }
+
+        let pattern = Cube::from(colors);
+        let cube = Cube::solved_state().apply(&[R, U_, F]);
+
+        let (tx, rx) = channel();
+        search_bounded(cube, &pattern, &ALL_TURNS[..18], 4, tx);
+        let plain = sent_algorithms(rx);
+
+        let (tx, rx) = channel();
+        search_broadening(cube, &pattern, &ALL_TURNS[..18], 2, 4, tx);
+        let results: Vec<SearchResult> = rx.iter().collect();
+
+        let broadened: Vec<Algorithm> = results.iter()
+            .filter_map(|res| match *res {
+                SearchResult::Algorithm(ref alg) => Some(alg.clone()),
+                _ => None,
+            })
+            .collect();
+
+        assert!(plain.len() > 5);
+        assert_eq!(sorted_strings(&broadened), sorted_strings(&plain));
+
+        // The depths and stats of all five passes, 2, 4, 8, 16 and 18 turns wide, and one
+        // Exhausted at the end
+        let count = |f: &dyn Fn(&SearchResult) -> bool| results.iter().filter(|res| f(res)).count();
+        assert_eq!(count(&|res| matches!(*res, SearchResult::Depth(_))), 5 * 4);
+        assert_eq!(count(&|res| matches!(*res, SearchResult::Stats { .. })), 5 * 4);
+        assert_eq!(count(&|res| matches!(*res, SearchResult::Exhausted(_))), 1);
+        assert!(matches!(results.last(), Some(&SearchResult::Exhausted(4))));
+    }
+
+    #[test]
+    fn broadening_stops_when_the_receiver_is_gone() {
+        let cube = random_cube();
+        let (tx, rx) = channel();
+
+        // Searching every pass to depth 20 would take far too long, so this only returns
+        // if dropping the receiver stops the pass being searched
+        let search = thread::spawn(move || {
+            search_broadening(cube, &Cube::solved_state(), &ALL_TURNS[..18], 1, 20, tx)
+        });
+
+        assert!(matches!(rx.recv(), Ok(SearchResult::Depth(1))));
+        drop(rx);
+
+        search.join().unwrap();
+    }
+
+    #[test]
+    fn special_states_are_what_they_say() {
+        let superflip = Cube::superflip();
+        let alg = "U R2 F B R B2 R U2 L B2 R U' D' R2 F R' L B2 U2 F2";
+
+        assert_eq!(Cube::solved_state().turn_str(alg).unwrap(), superflip);
+        assert_ne!(superflip, Cube::solved_state());
+
+        // Every piece home, and only the edges flipped
+        let cubies = superflip.cubies().unwrap();
+        assert_eq!(cubies.corners, [0, 1, 2, 3, 4, 5, 6, 7]);
+        assert_eq!(cubies.twists, [0; 8]);
+        assert_eq!(cubies.edges, [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11]);
+        assert_eq!(cubies.flips, [1; 12]);
+
+        // Faces in URFDLB order, so the opposite of face k is face k + 3
+        let solved: [Color; 54] = Cube::solved_state().into();
+        let checkerboard: [Color; 54] = Cube::checkerboard().into();
+
+        for k in 0..6 {
+            let opposite = solved[9 * ((k + 3) % 6) + 4];
+
+            for j in 0..9 {
+                let expected = if j % 2 == 0 { solved[9 * k + 4] } else { opposite };
+                assert_eq!(checkerboard[9 * k + j], expected, "facelet {}", 9 * k + j);
+            }
+        }
+    }
+
+    #[test]
+    fn incremental_face_matches_agree_with_matching_the_whole_cube() {
+        let mut rng = rand::thread_rng();
+
+        for _ in 0..500 {
+            let start = random_cube();
+            let alg: Vec<Turn> = (0..20).map(|_| *rng.choose(&ALL_TURNS).unwrap()).collect();
+            let states = start.states(&alg);
+
+            // Made from one of the cubes reached, so some of them match all of it
+            let mut colors: [Color; 54] = (*rng.choose(&states).unwrap()).into();
+
+            for _ in 0..rng.gen_range(0, 54) {
+                colors[rng.gen_range(0, 54)] = Color::Grey;
+            }
+
+            let pattern = Cube::from(colors);
+            let mut cube = start;
+            let mut matched = FaceMatches::new(&cube, &pattern);
+
+            for &turn in &alg {
+                let turned = cube.turn(turn);
+                let after = matched.after(turn, &turned, &pattern);
+
+                assert_eq!(after, FaceMatches::new(&turned, &pattern), "{} on {}", turn, cube);
+                assert_eq!(after.all(), turned.matches(&pattern));
+                assert_eq!(matched.all_after(turn, &turned, &pattern), after.all());
+
+                for (i, &face) in ALL_FACES.iter().enumerate() {
+                    if turn.changed_faces() & (1 << i) == 0 {
+                        assert_eq!(turned.face(face), cube.face(face), "{} moved {:?}", turn, face);
+                    }
+                }
+
+                cube = turned;
+                matched = after;
+            }
+        }
+    }
+
+    #[test]
+    fn bidirectional_search_stops_at_max_depth() {
+        let cube = Cube::solved_state().turn_str("R U R' U'").unwrap();
+        let options = SearchOptions { max_depth: Some(5), ..SearchOptions::default() };
+        let (tx, rx) = channel();
+
+        search_bidirectional(cube, &Cube::solved_state(), &ALL_TURNS[..18], options, tx);
+        let results: Vec<SearchResult> = rx.iter().collect();
+
+        assert!(matches!(results.last(), Some(&SearchResult::Exhausted(5))));
+
+        let algs: Vec<&Algorithm> = results.iter()
+            .filter_map(|res| match *res {
+                SearchResult::Algorithm(ref alg) => Some(alg),
+                _ => None,
+            })
+            .collect();
+
+        assert!(algs.iter().any(|alg| alg.to_string() == "U R U' R'"));
+
+        for alg in algs {
+            assert!(alg.len() <= 5);
+            assert_eq!(cube.apply(alg), Cube::solved_state());
+        }
+    }
+
+    #[test]
+    fn bidirectional_search_gives_up_at_the_deadline() {
+        let options = SearchOptions {
+            deadline: Some(Instant::now() + Duration::from_millis(200)),
+            ..SearchOptions::default()
+        };
+        let (tx, rx) = channel();
+
+        search_bidirectional(random_cube(), &Cube::solved_state(), &ALL_TURNS[..18], options, tx);
+
+        assert!(matches!(rx.iter().last(), Some(SearchResult::TimedOut(_))));
+
+        // A cancelled search just stops, like the regular one
+        let cancel = Arc::new(AtomicBool::new(true));
+        let options = SearchOptions { cancel: Some(cancel), ..SearchOptions::default() };
+        let (tx, rx) = channel();
+
+        search_bidirectional(random_cube(), &Cube::solved_state(), &ALL_TURNS[..18], options, tx);
+
+        assert!(rx.iter().all(|res| matches!(res, SearchResult::Depth(_))));
+    }
+
+    #[test]
+    fn grey_stickers_are_never_solvable() {
+        let solved: [Color; 54] = Cube::solved_state().into();
+
+        // A whole face grey, center included, so its stickers all match a center
+        let mut grey_face = solved;
+
+        for color in grey_face[..9].iter_mut() {
+            *color = Color::Grey;
+        }
+
+        // And a single grey sticker
+        let mut grey_sticker = solved;
+        grey_sticker[0] = Color::Grey;
+
+        for colors in &[grey_face, grey_sticker] {
+            let cube = Cube::from(*colors);
+
+            assert!(cube.cubies().is_none());
+            assert!(!cube.is_solvable());
+        }
+
+        assert!(Cube::solved_state().is_solvable());
+    }
+
+    #[test]
+    fn slices_are_the_outer_turns_undone_by_a_rotation() {
+        for &cube in &[Cube::solved_state(), random_cube()] {
+            let same = |a: &str, b: &str| cube.turn_str(a).unwrap() == cube.turn_str(b).unwrap();
+
+            // S follows F and E follows D, as z turns like F and y' like D
+            assert!(same("S", "F' B z"));
+            assert!(same("S'", "F B' z'"));
+            assert!(same("S2", "F2 B2 z2"));
+            assert!(same("E", "U D' y'"));
+            assert!(same("E'", "U' D y"));
+            assert!(same("E2", "U2 D2 y2"));
         }
     }
 
-    fn front2(&self) -> Self {
-        let up_to_down = ((self.up & PIECE6) >> SHIFT4) | ((self.up & PIECE7) >> SHIFT6) |
-                         ((self.up & PIECE8) >> SHIFT8);
+    #[test]
+    fn solved_states_are_equal() {
+        let mut states = HashSet::new();
+        states.insert(Cube::solved_state());
+        states.insert(Cube::solved_state());
 
-        let down_to_up = ((self.down & PIECE2) << SHIFT4) | ((self.down & PIECE1) << SHIFT6) |
-                         ((self.down & PIECE0) << SHIFT8);
+        assert_eq!(Cube::solved_state(), Cube::solved_state());
+        assert_eq!(states.len(), 1);
+        assert_ne!(Cube::solved_state(), Cube::solved_state().turn(Turn::U));
+    }
 
-        Cube {
-            up: (self.up & !MASK678) | down_to_up,
-            down: (self.down & !MASK012) | up_to_down,
-            left: (self.left & !MASK678) | (self.right & MASK678),
-            right: (self.right & !MASK678) | (self.left & MASK678),
-            front: Self::rotate_face2(self.front),
-            back: self.back,
+    #[test]
+    fn limited_search_sends_exactly_the_limit() {
+        // Only the up face has to be solved, which many algorithms do
+        let mut colors: [Color; 54] = Cube::solved_state().into();
+
+        for color in colors[9..].iter_mut() {
+            *color = Color::Grey;
         }
-    }
 
+        let pattern = Cube::from(colors);
+        let cube = Cube::solved_state().turn_str("R U' F").unwrap();
 
-    fn back(&self) -> Self {
-        let down_to_right = ((self.down & PIECE6) >> SHIFT4) | ((self.down & PIECE7) >> SHIFT6) |
-                            ((self.down & PIECE8) >> SHIFT8);
+        for &limit in &[1, 5, 20] {
+            let (tx, rx) = channel();
 
-        let left_to_down = ((self.left & PIECE2) << SHIFT4) | ((self.left & PIECE1) << SHIFT6) |
-                           ((self.left & PIECE0) << SHIFT8);
+            // Returns on its own once the limit is reached
+            search_limited(cube, &pattern, &ALL_TURNS[..18], limit, tx);
 
-        Cube {
-            up: (self.up & !MASK012) | (self.right & MASK012),
-            down: (self.down & !MASK678) | left_to_down,
-            left: (self.left & !MASK012) | (self.up & MASK012),
-            right: (self.right & !MASK012) | down_to_right,
-            front: self.front,
-            back: Self::rotate_face(self.back),
+            assert_eq!(sent_algorithms(rx).len(), limit);
         }
     }
 
-    fn back_(&self) -> Self {
-        let down_to_left = ((self.down & PIECE6) >> SHIFT4) | ((self.down & PIECE7) >> SHIFT6) |
-                           ((self.down & PIECE8) >> SHIFT8);
+    #[test]
+    fn dedup_takes_u_u_u_for_u_prime() {
+        use self::Turn::*;
 
-        let right_to_down = ((self.right & PIECE2) << SHIFT4) | ((self.right & PIECE1) << SHIFT6) |
-                            ((self.right & PIECE0) << SHIFT8);
+        // The form deduplication tells algorithms apart by
+        let form = |alg: &[Turn]| simplify(alg).canonical_string();
 
-        Cube {
-            up: (self.up & !MASK012) | (self.left & MASK012),
-            down: (self.down & !MASK678) | right_to_down,
-            left: (self.left & !MASK012) | down_to_left,
-            right: (self.right & !MASK012) | (self.up & MASK012),
-            front: self.front,
-            back: Self::rotate_face_(self.back),
-        }
+        assert_eq!(form(&[U, U, U]), form(&[U_]));
+        assert_eq!(form(&[U, U, U]), "U'");
+        assert_eq!(form(&[U, U, U, U]), "");
+        assert_ne!(form(&[U, U]), form(&[U_]));
+
+        // A deduplicated search reports every form once
+        let cube = Cube::solved_state().turn_str("R U R' U'").unwrap();
+        let options = SearchOptions { dedup: true, max_depth: Some(6), ..SearchOptions::default() };
+        let algs = algorithms(cube, &Cube::solved_state(), &ALL_TURNS[..18], options);
+        let forms: HashSet<String> = algs.iter().map(|alg| form(alg)).collect();
+
+        assert!(!algs.is_empty());
+        assert_eq!(forms.len(), algs.len());
     }
 
-    fn back2(&self) -> Self {
-        let down_to_up = ((self.down & PIECE6) >> SHIFT4) | ((self.down & PIECE7) >> SHIFT6) |
-                         ((self.down & PIECE8) >> SHIFT8);
+    #[test]
+    fn applying_an_algorithm_and_its_inverse_changes_nothing() {
+        use self::Turn::*;
 
-        let up_to_down = ((self.up & PIECE2) << SHIFT4) | ((self.up & PIECE1) << SHIFT6) |
-                         ((self.up & PIECE0) << SHIFT8);
+        let solved = Cube::solved_state();
+        assert_eq!(solved.apply(&[U, U, U, U]), solved);
+        assert_eq!(solved.apply(&[]), solved);
 
-        Cube {
-            up: (self.up & !MASK012) | down_to_up,
-            down: (self.down & !MASK678) | up_to_down,
-            left: (self.left & !MASK012) | (self.right & MASK012),
-            right: (self.right & !MASK012) | (self.left & MASK012),
-            front: self.front,
-            back: Self::rotate_face2(self.back),
+        for len in 0..30 {
+            let (cube, alg) = scramble(len, &mut rand::thread_rng());
+
+            assert_eq!(solved.apply(&alg), cube);
+            assert_eq!(cube.apply(&alg.inverse()), solved);
         }
     }
 
+    #[test]
+    fn facelets_round_trip_from_the_cube() {
+        let solved = concat!("UUUUUUUUU", "RRRRRRRRR", "FFFFFFFFF",
+                             "DDDDDDDDD", "LLLLLLLLL", "BBBBBBBBB");
 
-    fn up(&self) -> Self {
-        let right_to_front = ((self.right & PIECE0) << SHIFT2) | ((self.right & PIECE3) >> SHIFT2) |
-                             ((self.right & PIECE6) >> SHIFT6);
-
-        let front_to_left = ((self.front & PIECE2) << SHIFT6) | ((self.front & PIECE1) << SHIFT4) |
-                            ((self.front & PIECE0) << SHIFT2);
-
-        let left_to_back = ((self.left & PIECE8) >> SHIFT2) | ((self.left & PIECE5) << SHIFT2) |
-                           ((self.left & PIECE2) << SHIFT6);
+        assert_eq!(Cube::solved_state().to_facelets(), solved);
 
-        let back_to_right = ((self.back & PIECE6) >> SHIFT6) | ((self.back & PIECE7) >> SHIFT4) |
-                            ((self.back & PIECE8) >> SHIFT2);
+        for len in 0..30 {
+            let (cube, _) = scramble(len, &mut rand::thread_rng());
 
-        Cube {
-            up: Self::rotate_face(self.up),
-            down: self.down,
-            left: (self.left & !MASK258) | front_to_left,
-            right: (self.right & !MASK036) | back_to_right,
-            front: (self.front & !MASK012) | right_to_front,
-            back: (self.back & !MASK678) | left_to_back,
+            assert_eq!(Cube::from_facelets(&cube.to_facelets()), Ok(cube));
         }
     }
 
-    fn up_(&self) -> Self {
-        let right_to_back = ((self.right & PIECE0) << SHIFT6) | ((self.right & PIECE3) << SHIFT4) |
-                            ((self.right & PIECE6) << SHIFT2);
+    #[test]
+    fn facelets_round_trip_from_the_string() {
+        let superflip = "UBULURUFURURFRBRDRFUFLFRFDFDFDLDRDBDLULBLFLDLBUBRBLBDB";
 
-        let front_to_right = ((self.front & PIECE2) >> SHIFT2) | ((self.front & PIECE1) << SHIFT2) |
-                             ((self.front & PIECE0) << SHIFT6);
+        assert_eq!(Cube::from_facelets(superflip), Ok(Cube::superflip()));
+        assert_eq!(Cube::from_facelets(superflip).unwrap().to_facelets(), superflip);
 
-        let left_to_front = ((self.left & PIECE8) >> SHIFT6) | ((self.left & PIECE5) >> SHIFT4) |
-                            ((self.left & PIECE2) >> SHIFT2);
+        let mut rng = rand::thread_rng();
 
-        let back_to_left = ((self.back & PIECE6) << SHIFT2) | ((self.back & PIECE7) >> SHIFT2) |
-                           ((self.back & PIECE8) >> SHIFT6);
+        for _ in 0..30 {
+            let facelets = scramble(25, &mut rng).0.to_facelets();
 
-        Cube {
-            up: Self::rotate_face_(self.up),
-            down: self.down,
-            left: (self.left & !MASK258) | back_to_left,
-            right: (self.right & !MASK036) | front_to_right,
-            front: (self.front & !MASK012) | left_to_front,
-            back: (self.back & !MASK678) | right_to_back,
+            assert_eq!(Cube::from_facelets(&facelets).unwrap().to_facelets(), facelets);
         }
+
+        assert_eq!(Cube::from_facelets(&superflip[1..]), Err(FaceletError::WrongLength(53)));
+        assert_eq!(Cube::from_facelets(&superflip.replacen('U', "X", 1)),
+                   Err(FaceletError::InvalidCharacter('X')));
+        assert_eq!(Cube::from_facelets(&superflip.replacen('U', "R", 1)),
+                   Err(FaceletError::WrongCount('U', 8)));
     }
 
-    fn up2(&self) -> Self {
-        let right_to_left = ((self.right & PIECE0) << SHIFT8) | ((self.right & PIECE3) << SHIFT2) |
-                            ((self.right & PIECE6) >> SHIFT4);
+    #[test]
+    fn groups_need_one_color_whichever_it_is() {
+        // The up face one color and the front face another, or the same
+        let groups = Cube {
+            up: [1; 9],
+            down: [0; 9],
+            left: [0; 9],
+            right: [0; 9],
+            front: [2; 9],
+            back: [0; 9],
+        }.pack_groups();
+
+        let solved = Cube::solved_state();
+
+        assert!(solved.matches_groups(&groups));
+        assert!(solved.turn(Turn::Y).matches_groups(&groups));
+        assert!(!solved.turn(Turn::U).matches_groups(&groups));
+        assert!(!solved.turn(Turn::R).matches_groups(&groups));
+
+        // Facelets of one group on different faces, here the two centers
+        let mut centers = [[0; 9]; 6];
+        centers[0][4] = 3;
+        centers[4][4] = 3;
+
+        let groups = Cube {
+            up: centers[0],
+            down: centers[1],
+            left: centers[2],
+            right: centers[3],
+            front: centers[4],
+            back: centers[5],
+        }.pack_groups();
+
+        assert!(!solved.matches_groups(&groups));
+        assert!(Cube::unknown().matches_groups(&groups));
+    }
 
-        let front_to_back = ((self.front & PIECE2) << SHIFT4) | ((self.front & PIECE1) << SHIFT6) |
-                            ((self.front & PIECE0) << SHIFT8);
+    #[test]
+    fn quarter_turn_metric_counts_a_half_turn_as_two() {
+        let solved = Cube::solved_state();
+        let cube = solved.turn(Turn::U2);
+        let u2 = Algorithm(vec![Turn::U2]);
 
-        let left_to_right = ((self.left & PIECE8) >> SHIFT8) | ((self.left & PIECE5) >> SHIFT2) |
-                            ((self.left & PIECE2) << SHIFT4);
+        let search = |cost: Cost, max_depth: usize| {
+            let options = SearchOptions {
+                cost: Some(cost),
+                max_depth: Some(max_depth),
+                ..SearchOptions::default()
+            };
 
-        let back_to_front = ((self.back & PIECE6) >> SHIFT4) | ((self.back & PIECE7) >> SHIFT6) |
-                            ((self.back & PIECE8) >> SHIFT8);
+            algorithms(cube, &solved, &ALL_TURNS[..18], options)
+        };
 
-        Cube {
-            up: Self::rotate_face2(self.up),
-            down: self.down,
-            left: (self.left & !MASK258) | right_to_left,
-            right: (self.right & !MASK036) | left_to_right,
-            front: (self.front & !MASK012) | back_to_front,
-            back: (self.back & !MASK678) | front_to_back,
-        }
+        assert_eq!(search(turn_cost, 1), vec![u2.clone()]);
+        assert!(search(quarter_turn_cost, 1).is_empty());
+        assert!(search(quarter_turn_cost, 2).contains(&u2));
+        assert_eq!(quarter_turn_cost(Turn::U), 1);
+        assert_eq!(quarter_turn_cost(Turn::U_), 1);
     }
 
+    #[test]
+    fn reachability_check_needs_turns_moving_every_wrong_facelet() {
+        use self::Turn::*;
 
-    fn down(&self) -> Self {
-        let right_to_back = ((self.right & PIECE8) >> SHIFT6) | ((self.right & PIECE5) >> SHIFT4) |
-                            ((self.right & PIECE2) >> SHIFT2);
+        let solved = Cube::solved_state();
+        let cube = solved.turn(D);
 
-        let front_to_right = ((self.front & PIECE6) << SHIFT2) | ((self.front & PIECE7) >> SHIFT2) |
-                             ((self.front & PIECE8) >> SHIFT6);
+        // R and U never move the bottom left facelets of the sides
+        assert!(!reachability_check(&cube, &solved, &[R, R_, R2, U, U_, U2]));
+        assert!(reachability_check(&cube, &solved, &[D, D_, D2]));
+        assert!(reachability_check(&cube, &solved, &[L, R, F, B]));
 
-        let left_to_front = ((self.left & PIECE0) << SHIFT6) | ((self.left & PIECE3) << SHIFT4) |
-                            ((self.left & PIECE6) << SHIFT2);
+        // Facelets already right or grey in the goal need no turns at all
+        assert!(reachability_check(&cube, &cube, &[]));
+        assert!(reachability_check(&cube, &Cube::unknown(), &[]));
+        assert!(!reachability_check(&cube, &solved, &[]));
+    }
 
-        let back_to_left = ((self.back & PIECE2) >> SHIFT2) | ((self.back & PIECE1) << SHIFT2) |
-                           ((self.back & PIECE0) << SHIFT6);
+    #[test]
+    fn sorted_search_sends_shortest_then_alphabetically_first() {
+        // Only the up face has to be solved, so each depth has many algorithms
+        let mut colors: [Color; 54] = Cube::solved_state().into();
 
-        Cube {
-            up: self.up,
-            down: Self::rotate_face(self.down),
-            left: (self.left & !MASK036) | back_to_left,
-            right: (self.right & !MASK258) | front_to_right,
-            front: (self.front & !MASK678) | left_to_front,
-            back: (self.back & !MASK012) | right_to_back,
+        for color in colors[9..].iter_mut() {
+            *color = Color::Grey;
         }
+
+        let cube = Cube::solved_state().turn_str("R U' F").unwrap();
+        let options =
+            SearchOptions { sorted: true, max_depth: Some(5), ..SearchOptions::default() };
+        let algs = algorithms(cube, &Cube::from(colors), &ALL_TURNS[..18], options);
+
+        let keys: Vec<(usize, String)> =
+            algs.iter().map(|alg| (alg.len(), alg.to_string())).collect();
+        let mut sorted = keys.clone();
+        sorted.sort();
+        sorted.dedup();
+
+        assert!(keys.len() > 10);
+        assert_eq!(keys, sorted);
     }
 
-    fn down_(&self) -> Self {
-        let back_to_right = ((self.back & PIECE2) << SHIFT6) | ((self.back & PIECE1) << SHIFT4) |
-                            ((self.back & PIECE0) << SHIFT2);
+    #[test]
+    fn r_moves_twenty_stickers() {
+        let solved = Cube::solved_state();
 
-        let right_to_front = ((self.right & PIECE8) >> SHIFT2) | ((self.right & PIECE5) << SHIFT2) |
-                             ((self.right & PIECE2) << SHIFT6);
+        // The right face stays orange, so only the twelve stickers around it change color
+        let changed = solved.changed_facelets(&solved.turn(Turn::R));
+        assert_eq!(changed.len(), 12);
+        assert!(changed.iter().all(|&(face, _)| face != Face::Right && face != Face::Left));
 
-        let front_to_left = ((self.front & PIECE6) >> SHIFT6) | ((self.front & PIECE7) >> SHIFT4) |
-                            ((self.front & PIECE8) >> SHIFT2);
+        // Marking one sticker at a time tells all that move, the right face's eight too
+        let moved = ALL_FACES.iter()
+            .flat_map(|&face| (0..9).map(move |i| (face, i)))
+            .filter(|&(face, i)| {
+                let mut marked = Cube::from_faces([0; 6]);
+                *marked.face_mut(face) = 1 << (3 * i);
 
-        let left_to_back = ((self.left & PIECE0) << SHIFT2) | ((self.left & PIECE3) >> SHIFT2) |
-                           ((self.left & PIECE6) >> SHIFT6);
+                !marked.changed_facelets(&marked.turn(Turn::R)).is_empty()
+            })
+            .count();
 
-        Cube {
-            up: self.up,
-            down: Self::rotate_face_(self.down),
-            left: (self.left & !MASK036) | front_to_left,
-            right: (self.right & !MASK258) | back_to_right,
-            front: (self.front & !MASK678) | right_to_front,
-            back: (self.back & !MASK012) | left_to_back,
+        assert_eq!(moved, 20);
+        assert!(solved.changed_facelets(&solved).is_empty());
+    }
+
+    #[test]
+    fn colors_are_read_from_their_letters() {
+        use self::Color::*;
+
+        let letters = [('_', Grey), ('W', White), ('Y', Yellow), ('G', Green), ('B', Blue),
+                       ('R', Red), ('O', Orange)];
+
+        for &(letter, color) in &letters {
+            assert_eq!(Color::from_char(letter), Some(color));
+            assert_eq!(color.to_string(), letter.to_string());
         }
+
+        assert_eq!(Color::from_char('X'), None);
+        assert_eq!(Color::from_char('w'), None);
     }
 
-    fn down2(&self) -> Self {
-        let left_to_right = ((self.left & PIECE0) << SHIFT8) | ((self.left & PIECE3) << SHIFT2) |
-                            ((self.left & PIECE6) >> SHIFT4);
+    #[test]
+    fn chunks_that_are_no_color_are_invalid() {
+        let face = Cube::solved_state().up;
 
-        let back_to_front = ((self.back & PIECE2) << SHIFT4) | ((self.back & PIECE1) << SHIFT6) |
-                            ((self.back & PIECE0) << SHIFT8);
+        assert_eq!(nth_chunk(4, face), Ok(Color::Yellow));
 
-        let right_to_left = ((self.right & PIECE8) >> SHIFT8) | ((self.right & PIECE5) >> SHIFT2) |
-                            ((self.right & PIECE2) << SHIFT4);
+        // Chunk values go up to 7, but the colors stop at 6
+        let invalid = (face & !(0b111 << (3 * 4))) | (7 << (3 * 4));
 
-        let front_to_back = ((self.front & PIECE6) >> SHIFT4) | ((self.front & PIECE7) >> SHIFT6) |
-                            ((self.front & PIECE8) >> SHIFT8);
+        assert_eq!(nth_chunk(4, invalid), Err(InvalidFace { face: invalid, index: 4, chunk: 7 }));
+        assert_eq!(nth_chunk(3, invalid), Ok(Color::Yellow));
+    }
 
-        Cube {
-            up: self.up,
-            down: Self::rotate_face2(self.down),
-            left: (self.left & !MASK036) | right_to_left,
-            right: (self.right & !MASK258) | left_to_right,
-            front: (self.front & !MASK678) | back_to_front,
-            back: (self.back & !MASK012) | front_to_back,
+    #[test]
+    fn any_orientation_finds_the_goal_rotated_by_y() {
+        let cube = Cube::solved_state().turn_str("R U R' U'").unwrap();
+        let goal = Cube::solved_state().turn(Turn::Y);
+
+        let search = |any_orientation: bool| {
+            let options = SearchOptions {
+                any_orientation,
+                max_depth: Some(4),
+                ..SearchOptions::default()
+            };
+
+            algorithms(cube, &goal, &ALL_TURNS[..18], options)
+        };
+
+        // Outer turns never rotate the cube, so only a rotated goal can be reached
+        assert!(search(false).is_empty());
+
+        let algs = search(true);
+        assert!(algs.iter().any(|alg| alg.to_string() == "U R U' R'"));
+
+        for alg in &algs {
+            assert_eq!(cube.apply(alg), Cube::solved_state());
         }
     }
 
+    #[test]
+    fn states_end_where_apply_does() {
+        let (cube, alg) = scramble(20, &mut rand::thread_rng());
+        let start = random_cube();
+        let states = start.states(&alg);
 
-    pub fn turn(&self, t: Turn) -> Self {
-        use self::Turn::*;
+        assert_eq!(states.len(), alg.len() + 1);
+        assert_eq!(states[0], start);
+        assert_eq!(*states.last().unwrap(), start.apply(&alg));
+        assert_eq!(*Cube::solved_state().states(&alg).last().unwrap(), cube);
 
-        match t {
-            U => self.up(),
-            U_ => self.up_(),
-            U2 => self.up2(),
-            D => self.down(),
-            D_ => self.down_(),
-            D2 => self.down2(),
-            L => self.left(),
-            L_ => self.left_(),
-            L2 => self.left2(),
-            R => self.right(),
-            R_ => self.right_(),
-            R2 => self.right2(),
-            F => self.front(),
-            F_ => self.front_(),
-            F2 => self.front2(),
-            B => self.back(),
-            B_ => self.back_(),
-            B2 => self.back2(),
-            M => self.middle(),
-            M_ => self.middle_(),
-            M2 => self.middle2(),
+        for (i, pair) in states.windows(2).enumerate() {
+            assert_eq!(pair[0].turn(alg[i]), pair[1]);
         }
+
+        assert_eq!(start.states(&[]), vec![start]);
     }
-}
 
-fn nth_chunk(n: usize, face: u32) -> Color {
-    use self::Color::*;
+    #[test]
+    fn find_algorithms_returns_what_the_search_sends() {
+        let cube = Cube::solved_state().turn_str("R U R' U'").unwrap();
+        let found = find_algorithms(cube, &Cube::solved_state(), &ALL_TURNS[..18], 6);
+
+        let (tx, rx) = channel();
+        search_bounded(cube, &Cube::solved_state(), &ALL_TURNS[..18], 6, tx);
+
+        assert_eq!(found[0].to_string(), "U R U' R'");
+        assert_eq!(sorted_strings(&found), sorted_strings(&sent_algorithms(rx)));
+
+        // Shortest first, and every one solves the case
+        assert!(found.windows(2).all(|pair| pair[0].len() <= pair[1].len()));
+
+        for alg in &found {
+            assert_eq!(cube.apply(alg), Cube::solved_state());
+        }
 
-    match (face >> (n * 3)) & 0b111 {
-        0 => Grey,
-        1 => White,
-        2 => Yellow,
-        3 => Green,
-        4 => Blue,
-        5 => Red,
-        6 => Orange,
-        x => panic!("Invalid chunk {} in face {} at {}", x, face, n),
+        assert!(find_algorithms(cube, &Cube::solved_state(), &ALL_TURNS[..18], 3).is_empty());
     }
-}
 
-impl fmt::Display for Cube {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        // Back
+    #[test]
+    fn mirroring_twice_changes_nothing() {
+        for &plane in &[Mirror::LR, Mirror::UD, Mirror::FB] {
+            for _ in 0..20 {
+                let cube = random_cube();
 
-        writeln!(f,
-                 "        {}{}{}\n        {}{}{}\n        {}{}{}",
-                 nth_chunk(0, self.back),
-                 nth_chunk(1, self.back),
-                 nth_chunk(2, self.back),
+                assert_eq!(cube.mirror(plane).mirror(plane), cube);
+            }
 
-                 nth_chunk(3, self.back),
-                 nth_chunk(4, self.back),
-                 nth_chunk(5, self.back),
+            // The solved state itself has the mirrored faces swapped
+            assert_ne!(Cube::solved_state().mirror(plane), Cube::solved_state());
+        }
 
-                 nth_chunk(6, self.back),
-                 nth_chunk(7, self.back),
-                 nth_chunk(8, self.back))
-            .unwrap();
+        // Turning the mirrored cube by the mirrored turns mirrors the turned cube
+        let (cube, alg) = scramble(20, &mut rand::thread_rng());
+        let mirrored: Vec<Turn> = alg.iter().map(|&turn| mirror(turn)).collect();
 
-        // Down, left, up, right
+        assert_eq!(Cube::solved_state().mirror(Mirror::LR).apply(&mirrored),
+                   cube.mirror(Mirror::LR));
+    }
 
-        writeln!(f,
-                 "{}{}{} {}{}{} {}{}{} {}{}{}",
-                 nth_chunk(8, self.down),
-                 nth_chunk(7, self.down),
-                 nth_chunk(6, self.down),
+    #[test]
+    fn metrics_count_slices_half_turns_and_rotations() {
+        let metrics = |alg: &str| {
+            let alg = parse_algorithm(alg).unwrap();
+            (alg.htm(), alg.qtm(), alg.stm())
+        };
 
-                 nth_chunk(0, self.left),
-                 nth_chunk(1, self.left),
-                 nth_chunk(2, self.left),
+        assert_eq!(metrics(""), (0, 0, 0));
+        assert_eq!(metrics("R U R' U'"), (4, 4, 4));
+        assert_eq!(metrics("U2"), (1, 2, 1));
+        assert_eq!(metrics("M'"), (2, 2, 1));
+        assert_eq!(metrics("S2"), (2, 4, 1));
+        assert_eq!(metrics("x y2 z'"), (0, 0, 0));
+        assert_eq!(metrics("R U2 M' x S2"), (6, 9, 4));
+    }
 
-                 nth_chunk(0, self.up),
-                 nth_chunk(1, self.up),
-                 nth_chunk(2, self.up),
+    #[test]
+    fn turn_str_applies_valid_algorithms_only() {
+        use self::Turn::*;
 
-                 nth_chunk(0, self.right),
-                 nth_chunk(1, self.right),
-                 nth_chunk(2, self.right),
-        )
-            .unwrap();
+        let solved = Cube::solved_state();
 
-        writeln!(f,
-                 "{}{}{} {}{}{} {}{}{} {}{}{}",
-                 nth_chunk(5, self.down),
-                 nth_chunk(4, self.down),
-                 nth_chunk(3, self.down),
+        assert_eq!(solved.turn_str("R U R' U'"), Ok(solved.apply(&[R, U, R_, U_])));
+        assert_eq!(solved.turn_str(""), Ok(solved));
+        assert_eq!(solved.turn_str("  R2   x  "), Ok(solved.apply(&[R2, X])));
 
-                 nth_chunk(3, self.left),
-                 nth_chunk(4, self.left),
-                 nth_chunk(5, self.left),
+        assert_eq!(solved.turn_str("R Q"), Err(ParseError::InvalidTurn("Q".to_string())));
+        assert_eq!(solved.turn_str("R U3"), Err(ParseError::InvalidTurn("U3".to_string())));
+        assert_eq!(solved.turn_str("r"), Err(ParseError::InvalidTurn("r".to_string())));
+    }
 
-                 nth_chunk(3, self.up),
-                 nth_chunk(4, self.up),
-                 nth_chunk(5, self.up),
+    #[test]
+    fn search_cases_keeps_each_case_at_its_index() {
+        let solved = Cube::solved_state();
+        let sexy = solved.turn_str("R U R' U'").unwrap();
+        let sledgehammer = solved.turn_str("R' F R F'").unwrap();
 
-                 nth_chunk(3, self.right),
-                 nth_chunk(4, self.right),
-                 nth_chunk(5, self.right),
-        )
-            .unwrap();
+        let found = search_cases(&[(sexy, solved), (sledgehammer, solved)], &ALL_TURNS[..18], 5);
 
+        assert_eq!(found.len(), 2);
+        // Algorithms of the same length may come in any order
+        let alone = |cube: Cube| {
+            sorted_strings(&find_algorithms(cube, &solved, &ALL_TURNS[..18], 5))
+        };
 
-        writeln!(f,
-                 "{}{}{} {}{}{} {}{}{} {}{}{}",
-                 nth_chunk(2, self.down),
-                 nth_chunk(1, self.down),
-                 nth_chunk(0, self.down),
+        assert_eq!(sorted_strings(&found[0]), alone(sexy));
+        assert_eq!(sorted_strings(&found[1]), alone(sledgehammer));
+        assert_eq!(found[0][0].to_string(), "U R U' R'");
+        assert_eq!(found[1][0].to_string(), "F R' F' R");
 
-                 nth_chunk(6, self.left),
-                 nth_chunk(7, self.left),
-                 nth_chunk(8, self.left),
+        assert!(search_cases(&[], &ALL_TURNS[..18], 5).is_empty());
+    }
 
-                 nth_chunk(6, self.up),
-                 nth_chunk(7, self.up),
-                 nth_chunk(8, self.up),
+    #[test]
+    fn bytes_round_trip_and_stay_the_same() {
+        for _ in 0..50 {
+            let cube = random_cube();
 
-                 nth_chunk(6, self.right),
-                 nth_chunk(7, self.right),
-                 nth_chunk(8, self.right),
-        )
-            .unwrap();
+            assert_eq!(Cube::from_bytes(&cube.to_bytes()), Ok(cube));
+        }
 
-        // Front
+        // Saved bytes must still load after any change to the layout, so it is pinned here
+        let solved = [146, 36, 73, 2, 73, 146, 36, 1, 109, 219, 182, 5, 182, 109, 219, 6, 219, 182,
+                      109, 3, 36, 73, 146, 4];
 
-        writeln!(f,
-                 "        {}{}{}\n        {}{}{}\n        {}{}{}",
-                 nth_chunk(0, self.front),
-                 nth_chunk(1, self.front),
-                 nth_chunk(2, self.front),
+        assert_eq!(Cube::solved_state().to_bytes(), solved);
+        assert_eq!(Cube::from_bytes(&solved), Ok(Cube::solved_state()));
 
-                 nth_chunk(3, self.front),
-                 nth_chunk(4, self.front),
-                 nth_chunk(5, self.front),
+        assert_eq!(Cube::from_bytes(&solved[..23]), Err(BytesError::WrongLength(23)));
 
-                 nth_chunk(6, self.front),
-                 nth_chunk(7, self.front),
-                 nth_chunk(8, self.front))
+        let mut unused = solved;
+        unused[7] |= 0b1000_0000;
+        assert_eq!(Cube::from_bytes(&unused), Err(BytesError::UnusedBits(1)));
     }
-}
 
-fn search_helper(
-    cube: Cube,
-    last_turn: u8,
-    depth: usize,
-    max_depth: usize,
-    pattern: &Cube,
-    history: &mut [Turn],
-    allowed_turns: &[Turn],
-    tx: &Sender<SearchResult>
-) {
-    if depth > max_depth {
-        return;
+    #[test]
+    fn algorithms_parse_across_lines_and_comments() {
+        use self::Turn::*;
+
+        let text = concat!("// setup\n",
+                           "R U R' U'   // sexy move\n",
+                           "\n",
+                           "  F2 //\n",
+                           "M' x  \n",
+                           "// U D\n");
+
+        assert_eq!(parse_algorithm(text), Ok(Algorithm(vec![R, U, R_, U_, F2, M_, X])));
+        assert_eq!(parse_algorithm("R\r\nU"), Ok(Algorithm(vec![R, U])));
+        assert_eq!(parse_algorithm("// only a comment"), Ok(Algorithm::default()));
+        assert_eq!(parse_algorithm("R // fine\nU4 // not"),
+                   Err(ParseError::InvalidTurn("U4".to_string())));
     }
 
-    if depth == max_depth && cube.matches(pattern) {
-        let alg = history.iter().take(depth).map(|&turn| turn).collect();
+    #[test]
+    fn canonical_strings_merge_turns_of_a_face() {
+        let canonical = |alg: &str| parse_algorithm(alg).unwrap().canonical_string();
 
-        match tx.send(SearchResult::Algorithm(alg)) {
-            Ok(()) => {}
-            Err(_) => return,
-        }
+        assert_eq!(canonical("U U"), canonical("U2"));
+        assert_eq!(canonical("U U"), "U2");
+        assert_eq!(canonical("U' U'"), "U2");
+        assert_eq!(canonical("R U U' R'"), "");
+        assert_eq!(canonical("R U2 U R'"), canonical("R U' R'"));
 
-        return;
+        // Only turns of the same face merge, opposite faces are not reordered
+        assert_ne!(canonical("U D"), canonical("D U"));
     }
 
-    for &turn in allowed_turns.iter() {
-        if turn as u8 ^ last_turn > 0b11 {
-            history[depth] = turn;
-            search_helper(cube.turn(turn),
-                          turn as u8,
-                          depth + 1,
-                          max_depth,
-                          pattern,
-                          history,
-                          allowed_turns,
-                          tx);
+    #[test]
+    fn color_arrays_round_trip() {
+        for _ in 0..50 {
+            let cube = random_cube();
+            let colors: [Color; 54] = cube.into();
+
+            assert_eq!(Cube::from(colors), cube);
         }
+
+        // In facelet order, U1 to U9 first, and grey survives too
+        let mut colors: [Color; 54] = Cube::solved_state().into();
+        assert!(colors[..9].iter().all(|&color| color == Color::Yellow));
+
+        colors[0] = Color::Grey;
+        let cube = Cube::from(colors);
+
+        assert_eq!(<[Color; 54]>::from(cube)[..], colors[..]);
+        assert_eq!(cube.to_facelets().chars().next(), Some('_'));
     }
 
-}
+    #[test]
+    fn infeasible_searches_tell_why() {
+        use self::Turn::*;
 
-pub fn search(cube: Cube, pattern: &Cube, allowed_turns: &[Turn], tx: Sender<SearchResult>) {
-    let mut max_depth = 1;
+        let solved = Cube::solved_state();
+        let cube = random_cube();
+        let turns = &ALL_TURNS[..18];
 
-    loop {
-        match tx.send(SearchResult::Depth(max_depth)) {
-            Ok(()) => {}
-            Err(_) => return,
-        }
+        assert_eq!(check_feasible(&cube, &solved, turns), Ok(()));
+        assert_eq!(check_feasible(&swap_edges(cube), &solved, turns),
+                   Err(Infeasible::UnsolvableStart));
+        assert_eq!(check_feasible(&cube, &swap_edges(solved), turns),
+                   Err(Infeasible::UnsolvableGoal));
 
-        let senders: Vec<_> = allowed_turns.iter().map(|_| tx.clone()).collect();
+        // A grey sticker in the start leaves it one yellow short of the goal
+        let mut colors: [Color; 54] = cube.into();
+        let yellow = colors.iter().position(|&color| color == Color::Yellow).unwrap();
+        colors[yellow] = Color::Grey;
 
-        allowed_turns.into_par_iter().zip(senders).for_each(move |(&turn, sender)| {
-            let mut history = vec![turn; max_depth+1];
-            let cube = cube.turn(turn);
+        assert_eq!(check_feasible(&Cube::from(colors), &solved, turns),
+                   Err(Infeasible::MissingColors(vec![Color::Yellow])));
 
-            search_helper(cube,
-                          turn as u8,
-                          1,
-                          max_depth,
-                          pattern,
-                          &mut history,
-                          allowed_turns,
-                          &sender);
-        });
+        assert_eq!(check_feasible(&solved.turn(D), &solved, &[R, R_, R2, U, U_, U2]),
+                   Err(Infeasible::Unreachable));
+    }
 
-        max_depth += 1;
+    #[test]
+    fn turns_share_an_axis_with_their_opposite_face() {
+        use self::Turn::*;
+
+        assert_eq!(U.axis(), D.axis());
+        assert_eq!(U.axis(), Axis::UD);
+        assert_eq!(U2.axis(), D_.axis());
+        assert_ne!(U.axis(), R.axis());
+        assert_ne!(U.axis(), F.axis());
+
+        // Slices and rotations turn about the axis of the faces beside them
+        assert_eq!([E.axis(), Y.axis()], [Axis::UD; 2]);
+        assert_eq!([M.axis(), X.axis(), L.axis(), R.axis()], [Axis::LR; 4]);
+        assert_eq!([S.axis(), Z.axis(), F.axis(), B.axis()], [Axis::FB; 4]);
+
+        assert!(E.is_slice() && M.is_slice() && S.is_slice());
+        assert!(!U.is_slice() && !D.is_slice() && !Y.is_slice());
     }
 }
@@ -0,0 +1,89 @@
+use cube::*;
+
+use std::thread;
+use std::sync::mpsc::channel;
+
+// Every variant of each face given, so "RU" allows R, R', R2, U, U' and U2
+fn parse_turns(faces: &str) -> Result<Vec<Turn>, String> {
+    let mut turns = Vec::new();
+
+    for face in faces.chars() {
+        for suffix in &["", "'", "2"] {
+            let name = format!("{}{}", face, suffix);
+            turns.push(name.parse().map_err(|e: ParseError| e.to_string())?);
+        }
+    }
+
+    Ok(turns)
+}
+
+fn parse_cube(facelets: &str) -> Result<Cube, String> {
+    Cube::from_facelets(facelets).map_err(|e| e.to_string())
+}
+
+// Prints every algorithm found, one per line
+//
+// --from <facelets>   starting state, required
+// --to <facelets>     goal state, solved if not given
+// --turns <faces>     allowed faces like UDLRFBM, UDLRFB if not given
+// --max-depth <n>     stop after this depth instead of searching forever
+// --start-depth <n>   skip the depths below this one, already searched before
+// --threads <n>       search on this many threads instead of one per core
+pub fn run(args: &[String]) -> Result<(), String> {
+    let mut from = None;
+    let mut to = Cube::solved_state();
+    let mut allowed_turns = parse_turns("UDLRFB")?;
+    let mut max_depth = None;
+    let mut start_depth = None;
+    let mut threads = None;
+
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        if arg == "--headless" {
+            continue;
+        }
+
+        let value = args.next().ok_or_else(|| format!("Missing value for {}", arg))?;
+
+        match arg.as_str() {
+            "--from" => from = Some(parse_cube(value)?),
+            "--to" => to = parse_cube(value)?,
+            "--turns" => allowed_turns = parse_turns(value)?,
+            "--max-depth" => {
+                max_depth = Some(value.parse().map_err(|_| format!("Invalid depth: {}", value))?)
+            }
+            "--start-depth" => {
+                let invalid = |_| format!("Invalid depth: {}", value);
+                start_depth = Some(value.parse().map_err(invalid)?)
+            }
+            "--threads" => {
+                let invalid = |_| format!("Invalid thread count: {}", value);
+                threads = Some(value.parse().map_err(invalid)?)
+            }
+            _ => return Err(format!("Unknown argument: {}", arg)),
+        }
+    }
+
+    let from = from.ok_or("Missing --from")?;
+
+    check_feasible(&from, &to, &allowed_turns).map_err(|e| e.to_string())?;
+
+    let options = SearchOptions {
+        max_depth,
+        start_depth,
+        threads,
+        ..SearchOptions::default()
+    };
+    let (tx, rx) = channel();
+
+    thread::spawn(move || { search_with_options(from, &to, &allowed_turns, options, tx); });
+
+    for res in rx {
+        if let SearchResult::Algorithm(alg) = res {
+            println!("{}", alg);
+        }
+    }
+
+    Ok(())
+}
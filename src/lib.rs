@@ -0,0 +1,9 @@
+#[cfg(feature = "serde")]
+#[macro_use]
+extern crate serde;
+
+pub mod cube;
+pub mod solver;
+
+pub use cube::*;
+pub use solver::{solve, solve_cancellable, Solver};
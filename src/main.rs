@@ -1,6 +1,11 @@
+extern crate algfinder;
 #[macro_use]
 extern crate conrod;
 extern crate clipboard;
+extern crate rand;
+#[macro_use]
+extern crate serde;
+extern crate serde_json;
 
 use conrod::{widget, Colorable, Positionable, Widget, Sizeable, Borderable, Labelable};
 use conrod::backend::glium::glium::{self, DisplayBuild, Surface};
@@ -8,11 +13,18 @@ use conrod::backend::glium::glium::{self, DisplayBuild, Surface};
 use clipboard::ClipboardProvider;
 use clipboard::ClipboardContext;
 
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::io::{Read, Write};
 use std::thread;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc::{channel, Sender};
 
-pub mod cube;
+mod headless;
 
+use algfinder::cube;
+use algfinder::solve_cancellable;
 use cube::*;
 
 type PieceColors = Cube<[conrod::Color; 9]>;
@@ -20,6 +32,29 @@ type PieceColors = Cube<[conrod::Color; 9]>;
 const WIDTH: u32 = 1024;
 const HEIGHT: u32 = 768;
 
+// Keep the layout usable in very small and very large windows
+const MIN_FACEDIM: conrod::Scalar = 30.0;
+const MIN_FONT_SIZE: u32 = 8;
+const MAX_FONT_SIZE: u32 = 24;
+
+// Algorithms found beyond this many are counted but not listed.
+// They come shortest first, so the list keeps the shortest ones.
+const MAX_RESULTS: usize = 500;
+
+// Searches give up after this long unless the config says otherwise, in case the goal
+// can't be reached with the turns
+const SEARCH_SECONDS: u64 = 300;
+
+// Common sets of allowed turns, picked from a drop down list, as the faces whose turns are allowed
+const TURN_PRESETS: [(&str, &[Turn]); 5] = [
+    ("2-gen <R,U>", &[Turn::R, Turn::U]),
+    ("Roux <M,R,U>", &[Turn::M, Turn::R, Turn::U]),
+    ("<R,U,D>", &[Turn::R, Turn::U, Turn::D]),
+    ("All outer", &[Turn::U, Turn::D, Turn::L, Turn::R, Turn::F, Turn::B]),
+    ("All", &[Turn::U, Turn::D, Turn::L, Turn::R, Turn::F, Turn::B,
+              Turn::M, Turn::S, Turn::E, Turn::X, Turn::Y, Turn::Z]),
+];
+
 const COLORS: [conrod::Color; 7] = [conrod::color::WHITE,
                                     conrod::color::RED,
                                     conrod::color::BLUE,
@@ -28,14 +63,67 @@ const COLORS: [conrod::Color; 7] = [conrod::color::WHITE,
                                     conrod::color::ORANGE,
                                     conrod::color::GREY];
 
-const DEFAULT_PIECE_COLORS: PieceColors = PieceColors {
-    up: [conrod::color::YELLOW; 9],
-    down: [conrod::color::WHITE; 9],
-    left: [conrod::color::RED; 9],
-    right: [conrod::color::ORANGE; 9],
-    front: [conrod::color::GREEN; 9],
-    back: [conrod::color::BLUE; 9],
-};
+// The color of each face in the solved state, cycled through with a button.
+// The Western scheme in the orientations people hold it in, and the Japanese scheme,
+// which puts blue opposite white and yellow opposite green.
+const SCHEMES: [(&str, Cube<Color>); 4] = [("Western, yellow top",
+                                            Cube {
+                                                up: Color::Yellow,
+                                                down: Color::White,
+                                                left: Color::Red,
+                                                right: Color::Orange,
+                                                front: Color::Green,
+                                                back: Color::Blue,
+                                            }),
+                                           ("Western, white top",
+                                            Cube {
+                                                up: Color::White,
+                                                down: Color::Yellow,
+                                                left: Color::Orange,
+                                                right: Color::Red,
+                                                front: Color::Green,
+                                                back: Color::Blue,
+                                            }),
+                                           ("Western, red front",
+                                            Cube {
+                                                up: Color::White,
+                                                down: Color::Yellow,
+                                                left: Color::Green,
+                                                right: Color::Blue,
+                                                front: Color::Red,
+                                                back: Color::Orange,
+                                            }),
+                                           ("Japanese",
+                                            Cube {
+                                                up: Color::White,
+                                                down: Color::Blue,
+                                                left: Color::Orange,
+                                                right: Color::Red,
+                                                front: Color::Green,
+                                                back: Color::Yellow,
+                                            })];
+
+// The cube in the colors of Cube::solved_state, which are those of the first scheme and
+// the ones the solver knows, each color of the scheme becoming the one of its face there
+fn in_solved_colors(cube: &Cube, scheme: &Cube<Color>) -> Cube {
+    let solved = &SCHEMES[0].1;
+    let faces = [(scheme.up, solved.up),
+                 (scheme.down, solved.down),
+                 (scheme.left, solved.left),
+                 (scheme.right, solved.right),
+                 (scheme.front, solved.front),
+                 (scheme.back, solved.back)];
+
+    let mut colors: [Color; 54] = (*cube).into();
+
+    for color in colors.iter_mut() {
+        if let Some(&(_, solved)) = faces.iter().find(|&&(from, _)| from == *color) {
+            *color = solved;
+        }
+    }
+
+    Cube::from(colors)
+}
 
 fn to_cube_color(color: &conrod::Color) -> Color {
     use conrod::color::*;
@@ -62,86 +150,469 @@ fn to_cube_color(color: &conrod::Color) -> Color {
     }
 }
 
-fn to_cube(colors: &PieceColors) -> Cube {
-    let mut down: Vec<Color> = colors.down.iter().map(to_cube_color).collect();
-    down.reverse();
+fn to_conrod_color(color: Color) -> conrod::Color {
+    use conrod::color::*;
+    use cube::Color;
+
+    match color {
+        Color::Yellow => YELLOW,
+        Color::White => WHITE,
+        Color::Red => RED,
+        Color::Orange => ORANGE,
+        Color::Blue => BLUE,
+        Color::Green => GREEN,
+        Color::Grey => GREY,
+    }
+}
+
+// Keeps the layout of the GUI
+// Font sizes scale with the window but stay readable and fit their widgets
+fn font_size(size: conrod::Scalar) -> u32 {
+    std::cmp::max(MIN_FONT_SIZE, std::cmp::min(size as u32, MAX_FONT_SIZE))
+}
 
-    let cube: Cube<Vec<Color>> = Cube {
+fn to_color_lists(colors: &PieceColors) -> Cube<Vec<Color>> {
+    Cube {
         up: colors.up.iter().map(to_cube_color).collect(),
-        down: down,
+        down: colors.down.iter().map(to_cube_color).collect(),
         left: colors.left.iter().map(to_cube_color).collect(),
         right: colors.right.iter().map(to_cube_color).collect(),
         front: colors.front.iter().map(to_cube_color).collect(),
         back: colors.back.iter().map(to_cube_color).collect(),
+    }
+}
+
+fn from_color_lists(colors: &Cube<Vec<Color>>) -> Result<PieceColors, String> {
+    let face = |colors: &[Color]| {
+        if colors.len() != 9 {
+            return Err(format!("Expected 9 colors in a face but got {}", colors.len()));
+        }
+
+        let mut face = [conrod::color::GREY; 9];
+
+        for (piece, &color) in face.iter_mut().zip(colors) {
+            *piece = to_conrod_color(color);
+        }
+
+        Ok(face)
     };
 
+    Ok(PieceColors {
+        up: face(&colors.up)?,
+        down: face(&colors.down)?,
+        left: face(&colors.left)?,
+        right: face(&colors.right)?,
+        front: face(&colors.front)?,
+        back: face(&colors.back)?,
+    })
+}
+
+fn to_cube(colors: &PieceColors) -> Cube {
+    let mut cube = to_color_lists(colors);
+    cube.down.reverse();
     cube.pack()
 }
 
-fn search_helper(
+fn from_cube(cube: &Cube) -> PieceColors {
+    // Cubes made from the colors on screen only hold valid chunks,
+    // and unpacking always gives nine colors per face
+    let mut colors = cube.unpack().unwrap();
+    colors.down.reverse();
+
+    from_color_lists(&colors).unwrap()
+}
+
+fn paste_scramble(
+    clipboard: &mut ClipboardContext,
+    solved: &Cube
+) -> Result<PieceColors, String> {
+    let contents = clipboard.get_contents().map_err(|e| e.to_string())?;
+    let scramble = parse_algorithm(&contents).map_err(|e| e.to_string())?;
+
+    Ok(from_cube(&solved.apply(&scramble)))
+}
+
+// A file holding a scramble like the ones pasted
+fn load_scramble(path: &str, solved: &Cube) -> Result<PieceColors, String> {
+    let mut contents = String::new();
+    File::open(path).and_then(|mut file| file.read_to_string(&mut contents))
+        .map_err(|e| e.to_string())?;
+    let scramble = parse_algorithm(&contents).map_err(|e| e.to_string())?;
+
+    Ok(from_cube(&solved.apply(&scramble)))
+}
+
+// One algorithm per line, each depth headed by a comment.
+// Algorithms of a depth arrive before its stats, which close the depth.
+fn export_results(path: &str, results: &[SearchResult]) -> Result<(), String> {
+    let mut contents = String::new();
+    let mut depth_algs = Vec::new();
+
+    for res in results {
+        match *res {
+            SearchResult::Algorithm(ref alg) => depth_algs.push(alg.to_string()),
+            SearchResult::Stats { depth, .. } if !depth_algs.is_empty() => {
+                contents.push_str(&format!("// depth {}\n", depth));
+
+                for alg in depth_algs.drain(..) {
+                    contents.push_str(&alg);
+                    contents.push('\n');
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // From a depth the search was stopped in
+    for alg in depth_algs {
+        contents.push_str(&alg);
+        contents.push('\n');
+    }
+
+    File::create(path).and_then(|mut file| file.write_all(contents.as_bytes()))
+        .map_err(|e| e.to_string())
+}
+
+// From and to colors as they were before an edit
+type Snapshot = (PieceColors, PieceColors);
+
+const HISTORY_LIMIT: usize = 100;
+
+struct History {
+    undo: Vec<Snapshot>,
+    redo: Vec<Snapshot>,
+}
+
+impl History {
+    fn new() -> Self {
+        History {
+            undo: Vec::new(),
+            redo: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, snapshot: Snapshot) {
+        if self.undo.len() == HISTORY_LIMIT {
+            self.undo.remove(0);
+        }
+
+        self.undo.push(snapshot);
+        self.redo.clear();
+    }
+
+    fn undo(&mut self, current: Snapshot) -> Option<Snapshot> {
+        let previous = self.undo.pop()?;
+        self.redo.push(current);
+        Some(previous)
+    }
+
+    fn redo(&mut self, current: Snapshot) -> Option<Snapshot> {
+        let next = self.redo.pop()?;
+        self.undo.push(current);
+        Some(next)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Case {
+    from: Cube<Vec<Color>>,
+    to: Cube<Vec<Color>>,
+    allowed_turns: Vec<(Turn, bool)>,
+}
+
+// Settings kept from one run to the next
+#[derive(Serialize, Deserialize)]
+struct Config {
+    width: u32,
+    height: u32,
+    // Index into SCHEMES
+    scheme: usize,
+    allowed_turns: Vec<(Turn, bool)>,
+    // How long a search goes on before giving up. Configs saved before it was added
+    // get SEARCH_SECONDS.
+    #[serde(default = "default_search_seconds")]
+    search_seconds: u64,
+}
+
+fn default_search_seconds() -> u64 {
+    SEARCH_SECONDS
+}
+
+// The algorithm after or before the current one, skipping the other results.
+// Stays put at either end and starts from the ends when nothing is selected.
+fn step_algorithm(
+    results: &[SearchResult],
+    current: Option<usize>,
+    forward: bool
+) -> Option<usize> {
+    let is_algorithm = |i: &usize| matches!(results[*i], SearchResult::Algorithm(_));
+
+    let next = match (current, forward) {
+        (None, true) => (0..results.len()).find(is_algorithm),
+        (None, false) => (0..results.len()).rev().find(is_algorithm),
+        (Some(i), true) => (i + 1..results.len()).find(is_algorithm),
+        (Some(i), false) => (0..i).rev().find(is_algorithm),
+    };
+
+    next.or(current)
+}
+
+// How many results come before the first depth with algorithms is closed by its stats.
+// The search goes depth by depth, so the algorithms among them are of the first depth with
+// any, after every shallower depth was searched in full and came up empty. No algorithm
+// with the allowed turns is shorter, even if the search was stopped during that depth.
+fn optimal_results(results: &[SearchResult]) -> usize {
+    results.iter()
+        .position(|res| match *res {
+            SearchResult::Stats { found, .. } => found > 0,
+            _ => false,
+        })
+        .unwrap_or(results.len())
+}
+
+// The algorithm with its triggers in brackets, like "F [R U R' U'] F'"
+fn format_triggers(alg: &[Turn]) -> String {
+    let mut parts = Vec::new();
+    let mut i = 0;
+
+    for (start, trigger) in find_triggers(alg) {
+        let end = start + TRIGGERS[trigger].len();
+
+        parts.extend(alg[i..start].iter().map(|turn| turn.to_string()));
+        parts.push(format!("[{}]", format_algorithm(&alg[start..end])));
+        i = end;
+    }
+
+    parts.extend(alg[i..].iter().map(|turn| turn.to_string()));
+    parts.join(" ")
+}
+
+fn save_case(
+    path: &str,
+    from_colors: &PieceColors,
+    to_colors: &PieceColors,
+    allowed_turns: &[(Turn, bool)]
+) -> Result<(), String> {
+    let case = Case {
+        from: to_color_lists(from_colors),
+        to: to_color_lists(to_colors),
+        allowed_turns: allowed_turns.to_vec(),
+    };
+
+    let file = File::create(path).map_err(|e| e.to_string())?;
+
+    serde_json::to_writer_pretty(file, &case).map_err(|e| e.to_string())
+}
+
+fn load_case(path: &str) -> Result<(PieceColors, PieceColors, Vec<(Turn, bool)>), String> {
+    let file = File::open(path).map_err(|e| e.to_string())?;
+    let case: Case = serde_json::from_reader(file).map_err(|e| e.to_string())?;
+
+    Ok((from_color_lists(&case.from)?, from_color_lists(&case.to)?, case.allowed_turns))
+}
+
+// In the user's config directory, if there is a home directory to find it from
+fn config_path() -> Option<PathBuf> {
+    let home = std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE"));
+
+    std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| home.map(|home| PathBuf::from(home).join(".config")))
+        .map(|dir| dir.join("algfinder.json"))
+}
+
+fn load_config(path: &Path) -> Result<Config, String> {
+    let file = File::open(path).map_err(|e| e.to_string())?;
+
+    serde_json::from_reader(file).map_err(|e| e.to_string())
+}
+
+fn save_config(path: &Path, config: &Config) -> Result<(), String> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+
+    let file = File::create(path).map_err(|e| e.to_string())?;
+
+    serde_json::to_writer_pretty(file, config).map_err(|e| e.to_string())
+}
+
+// Turns missing from a loaded case keep their current setting
+fn set_allowed_turns(allowed_turns: &mut [(Turn, bool)], loaded: &[(Turn, bool)]) {
+    for &(turn, allowed) in loaded {
+        for entry in allowed_turns.iter_mut().filter(|entry| entry.0 == turn) {
+            entry.1 = allowed;
+        }
+    }
+}
+
+fn format_count(n: u64) -> String {
+    if n >= 1_000_000 {
+        format!("{:.1}M", n as f64 / 1_000_000.0)
+    } else if n >= 1_000 {
+        format!("{:.1}k", n as f64 / 1_000.0)
+    } else {
+        format!("{}", n)
+    }
+}
+
+// The turns toggled on, leaving out the half turns with quarter turns only
+fn enabled_turns(allowed_turns: &[(Turn, bool)], quarter_turns_only: bool) -> Vec<Turn> {
+    allowed_turns.iter()
+        .filter(|&&(turn, allowed)| allowed && !(quarter_turns_only && turn.is_half_turn()))
+        .map(|&(turn, _)| turn)
+        .collect()
+}
+
+// Searches on a new thread until cancelled or out of time
+fn spawn_search(
     from: Cube,
     to: Cube,
-    allowed_turns: Vec<(Turn, bool)>,
+    allowed_turns: &[(Turn, bool)],
+    quarter_turns_only: bool,
+    search_seconds: u64,
+    cancel: Arc<AtomicBool>,
     tx: Sender<SearchResult>
 ) {
-    let allowed: Vec<Turn> = allowed_turns.iter()
-        .filter_map(|&(turn, b)| if b { Some(turn) } else { None })
-        .collect();
+    let allowed = enabled_turns(allowed_turns, quarter_turns_only);
 
-    search(from, &to, &allowed, tx);
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(search_seconds);
+
+    let options = SearchOptions {
+        cancel: Some(cancel),
+        deadline: Some(deadline),
+        ..SearchOptions::default()
+    };
+
+    thread::spawn(move || { search_with_options(from, &to, &allowed, options, tx); });
 }
 
+// Solves on a new thread, reporting the solution like a search reports algorithms.
+// Stopped by the same flag as a search.
+fn spawn_solve(from: Cube, cancel: Arc<AtomicBool>, tx: Sender<SearchResult>) {
+    thread::spawn(move || {
+        let depth = match solve_cancellable(from, &cancel) {
+            Some(alg) => {
+                let depth = alg.len();
+                let _ = tx.send(SearchResult::Algorithm(alg));
+                depth
+            }
+            None => 0,
+        };
+
+        let _ = tx.send(SearchResult::Exhausted(depth));
+    });
+}
+
+// The argument following a flag like --case, if it was given
+fn flag_value(args: &[String], flag: &str) -> Option<String> {
+    args.iter().position(|arg| arg == flag).and_then(|i| args.get(i + 1)).cloned()
+}
 
 pub fn main() {
     use cube::Turn::*;
 
-    let mut allowed_turns = vec![(U, true),
-                                 (U_, true),
-                                 (U2, true),
-                                 (D, false),
-                                 (D_, false),
-                                 (D2, false),
-                                 (L, true),
-                                 (L_, true),
-                                 (L2, true),
-                                 (R, true),
-                                 (R_, true),
-                                 (R2, true),
-                                 (F, true),
-                                 (F_, true),
-                                 (F2, true),
-                                 (B, false),
-                                 (B_, false),
-                                 (B2, false),
-                                 (M, true),
-                                 (M_, true),
-                                 (M2, true)];
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    if args.iter().any(|arg| arg == "--headless") {
+        if let Err(e) = headless::run(&args) {
+            eprintln!("Failed to search: {}", e);
+            std::process::exit(1);
+        }
+
+        return;
+    }
+
+    // Turns of these faces are allowed until toggled
+    let allowed_by_default = [U, L, R, F, M];
+    let mut allowed_turns: Vec<(Turn, bool)> = ALL_TURNS.iter()
+        .map(|&turn| (turn, allowed_by_default.iter().any(|&face| face.same_face(turn))))
+        .collect();
+    // The preset last picked, until a turn is toggled by hand
+    let mut turn_preset: Option<usize> = None;
+    // Search without the half turns, even those toggled on
+    let mut quarter_turns_only = false;
+
+    // Falls back to the defaults without a config, which there is none of on the first run
+    let config_path = config_path();
+    let config = match config_path {
+        Some(ref path) if path.exists() => {
+            match load_config(path) {
+                Ok(config) => Some(config),
+                Err(e) => {
+                    println!("Failed to load settings from {}: {}", path.display(), e);
+                    None
+                }
+            }
+        }
+        _ => None,
+    };
+
+    // A window too small to use is no better than the default
+    let (width, height) = match config {
+        Some(ref config) if config.width >= 200 && config.height >= 150 => {
+            (config.width, config.height)
+        }
+        _ => (WIDTH, HEIGHT),
+    };
+
+    if let Some(ref config) = config {
+        set_allowed_turns(&mut allowed_turns, &config.allowed_turns);
+    }
+
+    // A search given no time at all would give up right away
+    let search_seconds = match config {
+        Some(ref config) if config.search_seconds > 0 => config.search_seconds,
+        _ => SEARCH_SECONDS,
+    };
+
+    // The file cases are saved to and loaded from
+    let case_path = flag_value(&args, "--case").unwrap_or_else(|| "case.json".to_string());
+    // The file the results are exported to
+    let export_path =
+        flag_value(&args, "--export").unwrap_or_else(|| "algorithms.txt".to_string());
 
     let mut searching = false;
+    // Whether the search is the solver, which has no depths to show
+    let mut solving = false;
+    let mut search_depth = 0;
+    // How long the search expects a depth to take, in milliseconds
+    let mut depth_estimate: Option<(usize, u64)> = None;
+    let mut search_started = std::time::Instant::now();
+    // Shown in the status line once a search is over
+    let mut search_outcome = String::new();
     let mut search_results: Vec<SearchResult> = Vec::new();
+    // Including those not listed because of MAX_RESULTS
+    let mut algorithms_found = 0;
+    // Index of the selected algorithm, whose result is shown in place of the goal
+    let mut preview: Option<usize> = None;
     let (mut algs_tx, mut algs_rx) = channel();
+    let mut cancel_search = Arc::new(AtomicBool::new(false));
 
     let mut clipboard: ClipboardContext = ClipboardProvider::new().unwrap();
 
     // Build the window.
     let display = glium::glutin::WindowBuilder::new()
         .with_vsync()
-        .with_dimensions(WIDTH, HEIGHT)
+        .with_dimensions(width, height)
         .with_title("Rubik's Cube Algorithm Finder")
         .with_multisampling(4)
         .build_glium()
         .unwrap();
 
 
-    let mut ui = conrod::UiBuilder::new([WIDTH as f64, HEIGHT as f64]).build();
+    let mut ui = conrod::UiBuilder::new([width as f64, height as f64]).build();
 
     widget_ids!(struct Ids {
         container, left_pane, right_pane,
-        canvas_from, canvas_to, from_faces, to_faces,
+        canvas_from, canvas_to, from_faces, to_faces, typed_alg, find_identities, show_triggers,
+        swap_button,
         color_picker_list, color_picker,
         canvas_algorithms, list_algorithms,
-        controls, search_button, reset_state_button, reset_goal_button,
-        allowed_turns, allowed_turns_list,
+        controls, status, status_text, search_button, reset_state_button, reset_goal_button,
+        save_case_button, load_case_button, paste_scramble_button, export_button, solve_button,
+        scramble_button, scheme_button, scramble_shorter_button, scramble_longer_button,
+        allowed_turns, allowed_turns_list, turn_presets, quarter_turns_only,
     });
 
     let ids = Ids::new(ui.widget_id_generator());
@@ -157,11 +628,31 @@ pub fn main() {
     let mut last_update = std::time::Instant::now();
     let mut ui_needs_update = true;
 
-    let mut from_colors = DEFAULT_PIECE_COLORS;
-    let mut to_colors = DEFAULT_PIECE_COLORS;
+    let mut scheme = match config {
+        Some(ref config) if config.scheme < SCHEMES.len() => config.scheme,
+        _ => 0,
+    };
+    let mut from_colors = from_cube(&Cube::solved_with(&SCHEMES[scheme].1));
+    let mut to_colors = from_colors;
 
     let mut current_color = conrod::color::GREY;
 
+    let mut history = History::new();
+
+    let mut scramble_length = 20;
+    // Typed into the box under the from state
+    let mut typed_alg = String::new();
+    let mut typed_alg_valid = true;
+    // Keep searching when the from state already matches the goal
+    let mut find_identities = false;
+    // Bracket the triggers in the algorithms found
+    let mut show_triggers = false;
+    // Whether the states have been swapped an odd number of times
+    let mut swapped = false;
+    // Whether the goal was painted last rather than the starting state
+    let mut editing_goal = false;
+    let mut ctrl_held = false;
+
     let sixteen_ms = std::time::Duration::from_millis(16);
 
     'main: loop {
@@ -172,12 +663,46 @@ pub fn main() {
 
         match algs_rx.try_recv() {
             Ok(res) => {
-                search_results.push(res);
+                match res {
+                    SearchResult::Depth(d) => search_depth = d,
+                    SearchResult::Estimate { depth, ms, .. } => depth_estimate = Some((depth, ms)),
+                    SearchResult::Exhausted(d) => {
+                        searching = false;
+
+                        let found = search_results.iter()
+                            .any(|res| matches!(*res, SearchResult::Algorithm(_)));
+
+                        search_outcome = if found {
+                            "Done".to_string()
+                        } else {
+                            format!("No solution up to depth {}", d)
+                        };
+                    }
+                    SearchResult::TimedOut(d) => {
+                        searching = false;
+                        search_outcome =
+                            format!("Gave up at depth {} after {}s", d, search_seconds);
+                    }
+                    res @ SearchResult::Algorithm(_) => {
+                        algorithms_found += 1;
+
+                        if algorithms_found <= MAX_RESULTS {
+                            search_results.push(res);
+                        }
+                    }
+                    res => search_results.push(res),
+                }
+
                 ui_needs_update = true;
             }
             Err(_) => {}
         }
 
+        // Keeps the elapsed time in the status line ticking
+        if searching {
+            ui_needs_update = true;
+        }
+
         let events: Vec<_> = display.poll_events().collect();
 
         if events.is_empty() && !ui_needs_update {
@@ -188,22 +713,140 @@ pub fn main() {
         ui_needs_update = false;
         last_update = std::time::Instant::now();
 
+        let mut search_key_pressed = false;
+        let mut stop_key_pressed = false;
+        let mut undo_key_pressed = false;
+        let mut redo_key_pressed = false;
+        let mut up_key_pressed = false;
+        let mut down_key_pressed = false;
+        let mut dropped_file = None;
+
         for event in events {
+            use glium::glutin::{Event, ElementState, VirtualKeyCode};
+
             if let Some(event) = conrod::backend::winit::convert(event.clone(), &display) {
                 ui.handle_event(event);
                 ui_needs_update = true;
             }
 
             match event {
-                glium::glutin::Event::Closed => break 'main,
+                Event::Closed => break 'main,
+                Event::KeyboardInput(ElementState::Pressed, _, Some(VirtualKeyCode::Return)) => {
+                    search_key_pressed = true
+                }
+                Event::KeyboardInput(ElementState::Pressed, _, Some(VirtualKeyCode::Escape)) => {
+                    stop_key_pressed = true
+                }
+                Event::KeyboardInput(ElementState::Pressed, _, Some(VirtualKeyCode::Up)) => {
+                    up_key_pressed = true
+                }
+                Event::KeyboardInput(ElementState::Pressed, _, Some(VirtualKeyCode::Down)) => {
+                    down_key_pressed = true
+                }
+                Event::KeyboardInput(state, _, Some(VirtualKeyCode::LControl)) |
+                Event::KeyboardInput(state, _, Some(VirtualKeyCode::RControl)) => {
+                    ctrl_held = state == ElementState::Pressed
+                }
+                Event::KeyboardInput(ElementState::Pressed, _, Some(VirtualKeyCode::Z))
+                    if ctrl_held => undo_key_pressed = true,
+                Event::KeyboardInput(ElementState::Pressed, _, Some(VirtualKeyCode::Y))
+                    if ctrl_held => redo_key_pressed = true,
+                Event::DroppedFile(path) => dropped_file = Some(path),
+                // Lays out again at the new size even if conrod made nothing of the event
+                Event::Resized(..) => ui_needs_update = true,
                 _ => {}
             }
         }
 
+        // Keys pressed while typing an algorithm edit it rather than act as shortcuts
+        if ui.global_input().current.widget_capturing_keyboard == Some(ids.typed_alg) {
+            search_key_pressed = false;
+            stop_key_pressed = false;
+            undo_key_pressed = false;
+            redo_key_pressed = false;
+            up_key_pressed = false;
+            down_key_pressed = false;
+        }
+
+        // The arrows select algorithms, enter copies the selected one instead of searching
+        // and escape clears the selection when there is no search to stop
+        if up_key_pressed || down_key_pressed {
+            preview = step_algorithm(&search_results, preview, down_key_pressed);
+        }
+
+        if !searching {
+            if let Some(i) = preview {
+                if search_key_pressed {
+                    if let SearchResult::Algorithm(ref alg) = search_results[i] {
+                        match clipboard.set_contents(alg.to_string()) {
+                            Ok(()) => {}
+                            Err(e) => println!("Failed to copy to clipboard: {}", e),
+                        }
+                    }
+
+                    search_key_pressed = false;
+                }
+
+                if stop_key_pressed {
+                    preview = None;
+                }
+            }
+        }
+
+        let snapshot = if undo_key_pressed {
+            history.undo((from_colors, to_colors))
+        } else if redo_key_pressed {
+            history.redo((from_colors, to_colors))
+        } else {
+            None
+        };
+
+        if let Some((from, to)) = snapshot {
+            from_colors = from;
+            to_colors = to;
+        }
+
+        let before_edit = (from_colors, to_colors);
+        let mut edited = false;
+
+        let solved = Cube::solved_with(&SCHEMES[scheme].1);
+
+        // A dropped .txt scramble sets the from state, a dropped .json case loads everything
+        if let Some(path) = dropped_file {
+            let name = path.to_string_lossy().into_owned();
+
+            match path.extension().and_then(|extension| extension.to_str()) {
+                Some("txt") => {
+                    match load_scramble(&name, &solved) {
+                        Ok(colors) => {
+                            from_colors = colors;
+                            edited = true;
+                        }
+                        Err(e) => println!("Failed to load scramble from {}: {}", name, e),
+                    }
+                }
+                Some("json") => {
+                    match load_case(&name) {
+                        Ok((from, to, turns)) => {
+                            from_colors = from;
+                            to_colors = to;
+                            edited = true;
+                            set_allowed_turns(&mut allowed_turns, &turns);
+                            turn_preset = None;
+                        }
+                        Err(e) => println!("Failed to load case from {}: {}", name, e),
+                    }
+                }
+                _ => println!("Failed to load {}: not a .txt scramble or .json case", name),
+            }
+        }
+
         {
             let ui = &mut ui.set_widgets();
 
-            let facedim = ui.win_h / 6.5;
+            // The cubes fill the height, unless that leaves too little width for the rest
+            let facedim = (ui.win_h / 6.5).min(ui.win_w / 8.0).max(MIN_FACEDIM);
+            let turns_w = (0.13 * (ui.win_w - 4.0 * facedim)).max(60.0).min(160.0);
 
             let lpane = [(ids.canvas_from,
                           widget::Canvas::new()
@@ -220,7 +863,11 @@ pub fn main() {
 
             let rpane = [(ids.controls,
                           widget::Canvas::new()
-                              .length_weight(0.1)
+                              .length_weight(0.3)
+                              .color(conrod::color::WHITE)),
+                         (ids.status,
+                          widget::Canvas::new()
+                              .length((0.04 * ui.win_w).max(20.0).min(48.0))
                               .color(conrod::color::WHITE)),
                          (ids.canvas_algorithms,
                           widget::Canvas::new().color(conrod::color::WHITE))];
@@ -233,7 +880,7 @@ pub fn main() {
                                    .length(4.0 * facedim)
                                    .flow_down(&lpane)),
                               (ids.right_pane, widget::Canvas::new().flow_down(&rpane)),
-                              (ids.allowed_turns, widget::Canvas::new().length_weight(0.15))])
+                              (ids.allowed_turns, widget::Canvas::new().length(turns_w))])
                 .set(ids.container, ui);
 
             // Cube
@@ -243,6 +890,13 @@ pub fn main() {
 
             let missing_colors = from.missing_colors(&to);
 
+            // Painting these adds no more stickers to the cube painted last
+            let full_colors = if editing_goal { to.full_colors() } else { from.full_colors() };
+
+            let feasible = check_feasible(&from,
+                                          &to,
+                                          &enabled_turns(&allowed_turns, quarter_turns_only));
+
             // Color picker
 
             let color_padding_h = 0.15 * 0.5 * facedim;
@@ -259,14 +913,19 @@ pub fn main() {
                 let color = COLORS[item.col];
                 let cube_color = to_cube_color(&color);
                 let missing = missing_colors.contains(&cube_color);
+                let full = full_colors.contains(&cube_color);
 
-                let border_color = if missing && color != current_color {
+                let border_color = if color == current_color {
+                    conrod::color::BLACK
+                } else if missing {
                     conrod::color::WHITE
+                } else if full {
+                    conrod::color::DARK_RED
                 } else {
                     conrod::color::BLACK
                 };
 
-                let border = if missing || color == current_color {
+                let border = if missing || full || color == current_color {
                     5.0
                 } else {
                     1.0
@@ -284,84 +943,357 @@ pub fn main() {
 
             // Controls
 
-            let controls_font_size = (0.025 * ui.win_w) as u32;
+            // Also fits the buttons under and over the cubes
+            let controls_font_size = font_size((0.02 * ui.win_w).min(0.2 * facedim));
             let control_w = ui.w_of(ids.controls).unwrap_or_default() / 3.0;
-
-            if widget::Button::new()
-                .w(control_w)
-                .h_of(ids.controls)
-                .mid_left_of(ids.controls)
-                .label(if searching { "Stop" } else { "Search" })
+            let control_h = ui.h_of(ids.controls).unwrap_or_default() / 3.0;
+
+            // Enter starts a search and escape stops it, just like the button
+            let search_clicked = widget::Button::new()
+                .w_h(control_w, control_h)
+                .top_left_of(ids.controls)
+                .label(match feasible {
+                    _ if searching => "Stop",
+                    Ok(()) => "Search",
+                    Err(Infeasible::MissingColors(_)) => "Missing colors",
+                    Err(Infeasible::Unreachable) => "Unreachable",
+                    Err(_) => "Unsolvable",
+                })
+                .label_color(if searching || feasible.is_ok() {
+                    conrod::color::BLACK
+                } else {
+                    conrod::color::DARK_RED
+                })
                 .label_font_size(controls_font_size)
                 .set(ids.search_button, ui)
-                .was_clicked() {
-                if searching {
-                    searching = false;
-                    let (new_tx, new_rx) = channel();
-                    algs_tx = new_tx;
-                    algs_rx = new_rx;
-                } else {
-                    if missing_colors.is_empty() {
+                .was_clicked();
+
+            if searching && (search_clicked || stop_key_pressed) {
+                searching = false;
+                search_outcome = format!("Stopped at depth {}", search_depth);
+                cancel_search.store(true, Ordering::SeqCst);
+                let (new_tx, new_rx) = channel();
+                algs_tx = new_tx;
+                algs_rx = new_rx;
+            } else if !searching && (search_clicked || search_key_pressed) {
+                if feasible.is_ok() {
+                    search_results.clear();
+                    algorithms_found = 0;
+                    preview = None;
+
+                    // Otherwise the search would go on finding longer and longer
+                    // algorithms that change nothing
+                    if from.matches(&to) && !find_identities {
+                        search_results.push(SearchResult::Algorithm(Algorithm::default()));
+                        algorithms_found = 1;
+                        search_outcome = "Done".to_string();
+                    } else {
                         searching = true;
-                        search_results.clear();
-                        let turns = allowed_turns.clone();
-                        let tx = algs_tx.clone();
-
-                        thread::spawn(move || { search_helper(from, to, turns, tx); });
+                        solving = false;
+                        search_depth = 0;
+                        depth_estimate = None;
+                        search_started = std::time::Instant::now();
+                        cancel_search = Arc::new(AtomicBool::new(false));
+
+                        spawn_search(from,
+                                     to,
+                                     &allowed_turns,
+                                     quarter_turns_only,
+                                     search_seconds,
+                                     cancel_search.clone(),
+                                     algs_tx.clone());
                     }
                 }
             }
 
             if widget::Button::new()
-                .w(control_w)
-                .h_of(ids.controls)
-                .middle_of(ids.controls)
+                .w_h(control_w, control_h)
+                .mid_top_of(ids.controls)
                 .label("Reset state")
                 .label_font_size(controls_font_size)
                 .set(ids.reset_state_button, ui)
                 .was_clicked() {
-                from_colors = DEFAULT_PIECE_COLORS;
+                from_colors = from_cube(&solved);
+                edited = true;
             }
 
             if widget::Button::new()
-                .w(control_w)
-                .h_of(ids.controls)
-                .mid_right_of(ids.controls)
+                .w_h(control_w, control_h)
+                .top_right_of(ids.controls)
                 .label("Reset goal")
                 .label_font_size(controls_font_size)
                 .set(ids.reset_goal_button, ui)
                 .was_clicked() {
-                to_colors = DEFAULT_PIECE_COLORS;
+                to_colors = from_cube(&solved);
+                edited = true;
+            }
+
+            if widget::Button::new()
+                .w_h(control_w, control_h)
+                .mid_left_of(ids.controls)
+                .label("Save case")
+                .label_font_size(controls_font_size)
+                .set(ids.save_case_button, ui)
+                .was_clicked() {
+                match save_case(&case_path, &from_colors, &to_colors, &allowed_turns) {
+                    Ok(()) => {}
+                    Err(e) => println!("Failed to save case to {}: {}", case_path, e),
+                }
+            }
+
+            if widget::Button::new()
+                .w_h(control_w, control_h)
+                .middle_of(ids.controls)
+                .label("Load case")
+                .label_font_size(controls_font_size)
+                .set(ids.load_case_button, ui)
+                .was_clicked() {
+                match load_case(&case_path) {
+                    Ok((from, to, turns)) => {
+                        from_colors = from;
+                        to_colors = to;
+                        edited = true;
+                        set_allowed_turns(&mut allowed_turns, &turns);
+                        turn_preset = None;
+                    }
+                    Err(e) => println!("Failed to load case from {}: {}", case_path, e),
+                }
+            }
+
+            if widget::Button::new()
+                .w_h(control_w, control_h)
+                .mid_right_of(ids.controls)
+                .label("Paste scramble")
+                .label_font_size(controls_font_size)
+                .set(ids.paste_scramble_button, ui)
+                .was_clicked() {
+                match paste_scramble(&mut clipboard, &solved) {
+                    Ok(colors) => {
+                        from_colors = colors;
+                        edited = true;
+                    }
+                    Err(e) => println!("Failed to paste scramble: {}", e),
+                }
+            }
+
+            if widget::Button::new()
+                .w_h(control_w, control_h)
+                .bottom_left_of(ids.controls)
+                .label(&format!("Scramble {}", scramble_length))
+                .label_font_size(controls_font_size)
+                .set(ids.scramble_button, ui)
+                .was_clicked() {
+                let (_, alg) = scramble(scramble_length, &mut rand::thread_rng());
+                from_colors = from_cube(&solved.apply(&alg));
+                edited = true;
+
+                // Copied so the scramble can be checked on a real cube
+                match clipboard.set_contents(alg.to_string()) {
+                    Ok(()) => {}
+                    Err(e) => println!("Failed to copy to clipboard: {}", e),
+                }
+            }
+
+            if widget::Button::new()
+                .w_h(control_w, control_h)
+                .mid_bottom_of(ids.controls)
+                .label(SCHEMES[scheme].0)
+                .label_font_size(controls_font_size)
+                .set(ids.scheme_button, ui)
+                .was_clicked() {
+                scheme = (scheme + 1) % SCHEMES.len();
+            }
+
+            if widget::Button::new()
+                .w_h(control_w / 2.0, control_h)
+                .bottom_right_of(ids.controls)
+                .label("+")
+                .label_font_size(controls_font_size)
+                .set(ids.scramble_longer_button, ui)
+                .was_clicked() {
+                scramble_length += 1;
+            }
+
+            if widget::Button::new()
+                .w_h(control_w / 2.0, control_h)
+                .left_from(ids.scramble_longer_button, 0.0)
+                .label("-")
+                .label_font_size(controls_font_size)
+                .set(ids.scramble_shorter_button, ui)
+                .was_clicked() && scramble_length > 1 {
+                scramble_length -= 1;
+            }
+
+            // Status
+
+            let shown = if algorithms_found > MAX_RESULTS {
+                format!(", showing {} of {} algorithms", MAX_RESULTS, algorithms_found)
+            } else {
+                String::new()
+            };
+
+            let status = if searching && solving {
+                format!("Solving... {}s", search_started.elapsed().as_secs())
+            } else if searching {
+                // Only worth showing for depths taking a while
+                let expected = match depth_estimate {
+                    Some((depth, ms)) if depth == search_depth && ms >= 1000 => {
+                        format!(", about {}s for this depth", ms / 1000)
+                    }
+                    _ => String::new(),
+                };
+
+                format!("Searching depth {}... {}s{}{}",
+                        search_depth,
+                        search_started.elapsed().as_secs(),
+                        expected,
+                        shown)
+            } else if let Err(ref e) = feasible {
+                e.to_string()
+            } else {
+                format!("{}{}", search_outcome, shown)
+            };
+
+            widget::Text::new(&status)
+                .middle_of(ids.status)
+                .font_size(controls_font_size)
+                .set(ids.status_text, ui);
+
+            // The solver needs every sticker of the starting state
+            let can_solve = from.is_solvable() && missing_colors.is_empty();
+
+            if widget::Button::new()
+                .w_h(control_w / 2.0, ui.h_of(ids.status).unwrap_or_default())
+                .mid_left_of(ids.status)
+                .label("Solve")
+                .label_color(if can_solve {
+                    conrod::color::BLACK
+                } else {
+                    conrod::color::DARK_RED
+                })
+                .label_font_size(controls_font_size)
+                .set(ids.solve_button, ui)
+                .was_clicked() && can_solve && !searching {
+                searching = true;
+                solving = true;
+                search_depth = 0;
+                depth_estimate = None;
+                search_started = std::time::Instant::now();
+                search_results.clear();
+                algorithms_found = 0;
+                preview = None;
+                cancel_search = Arc::new(AtomicBool::new(false));
+
+                spawn_solve(in_solved_colors(&from, &SCHEMES[scheme].1),
+                            cancel_search.clone(),
+                            algs_tx.clone());
+            }
+
+            if algorithms_found > 0 && widget::Button::new()
+                .w_h(control_w / 2.0, ui.h_of(ids.status).unwrap_or_default())
+                .mid_right_of(ids.status)
+                .label("Export")
+                .label_font_size(controls_font_size)
+                .set(ids.export_button, ui)
+                .was_clicked() {
+                match export_results(&export_path, &search_results) {
+                    Ok(()) => {}
+                    Err(e) => println!("Failed to export results to {}: {}", export_path, e),
+                }
             }
 
             // Allowed turns
 
-            let (mut items, _) = widget::List::flow_down(allowed_turns.len())
-                .item_size(ui.win_h / allowed_turns.len() as conrod::Scalar)
+            // A toggle for every turn at once, then each face's toggle above its three turns.
+            // ALL_TURNS keeps the turns of each face together, so groups are runs of three.
+            let groups = allowed_turns.len() / 3;
+            let presets_h = (ui.win_h / 24.0).max(20.0);
+
+            let preset_names: Vec<&str> = TURN_PRESETS.iter().map(|&(name, _)| name).collect();
+
+            for i in widget::DropDownList::new(&preset_names, turn_preset)
+                .w_of(ids.allowed_turns)
+                .h(presets_h)
+                .mid_top_of(ids.allowed_turns)
+                .max_visible_items(TURN_PRESETS.len())
+                .label("Presets")
+                .label_font_size(font_size(0.02 * ui.win_h))
+                .set(ids.turn_presets, ui) {
+                let faces = TURN_PRESETS[i].1;
+
+                for entry in &mut allowed_turns {
+                    entry.1 = faces.iter().any(|&face| face.same_face(entry.0));
+                }
+
+                turn_preset = Some(i);
+            }
+
+            let (mut items, scrollbar) = widget::List::flow_down(1 + 4 * groups)
+                .item_size((ui.win_h / 36.0).max(16.0))
                 .scrollbar_on_top()
-                .middle_of(ids.allowed_turns)
-                .wh_of(ids.allowed_turns)
+                .mid_top_with_margin_on(ids.allowed_turns, presets_h)
+                .w_of(ids.allowed_turns)
+                .h(ui.h_of(ids.allowed_turns).unwrap_or_default() - 2.0 * presets_h)
                 .set(ids.allowed_turns_list, ui);
 
             while let Some(item) = items.next(ui) {
-                let (turn, allowed) = allowed_turns[item.i];
-                let label = format!("{}", turn);
+                // The turns this row toggles
+                let (range, label, color) = if item.i == 0 {
+                    (0..allowed_turns.len(), "All".to_string(), conrod::color::DARK_BLUE)
+                } else if (item.i - 1) % 4 == 0 {
+                    let first = 3 * ((item.i - 1) / 4);
+                    (first..first + 3,
+                     format!("{} *", allowed_turns[first].0),
+                     conrod::color::BLUE)
+                } else {
+                    let turn = 3 * ((item.i - 1) / 4) + (item.i - 1) % 4 - 1;
+
+                    // Greyed out while ignored, but still toggleable
+                    (turn..turn + 1,
+                     format!("{}", allowed_turns[turn].0),
+                     if quarter_turns_only && allowed_turns[turn].0.is_half_turn() {
+                         conrod::color::GREY
+                     } else {
+                         conrod::color::LIGHT_BLUE
+                     })
+                };
+
+                let allowed = allowed_turns[range.clone()].iter().all(|&(_, allowed)| allowed);
 
                 let toggle = widget::Toggle::new(allowed)
                     .label(&label)
                     .label_color(conrod::color::WHITE)
-                    .label_font_size((0.025 * ui.win_h) as u32)
-                    .color(conrod::color::LIGHT_BLUE);
+                    .label_font_size(font_size(0.02 * ui.win_h))
+                    .color(color);
 
                 for v in item.set(toggle, ui) {
-                    allowed_turns[item.i] = (turn, v);
+                    for entry in &mut allowed_turns[range.clone()] {
+                        entry.1 = v;
+                    }
+
+                    turn_preset = None;
                 }
             }
 
+            if let Some(s) = scrollbar {
+                s.set(ui)
+            }
+
+            for value in widget::Toggle::new(quarter_turns_only)
+                .w_of(ids.allowed_turns)
+                .h(presets_h)
+                .mid_bottom_of(ids.allowed_turns)
+                .label("Quarter only")
+                .label_color(conrod::color::WHITE)
+                .label_font_size(font_size(0.02 * ui.win_h))
+                .color(conrod::color::DARK_BLUE)
+                .set(ids.quarter_turns_only, ui) {
+                quarter_turns_only = value;
+            }
+
             // Search results
 
-            let alg_font_size = std::cmp::min((0.03 * ui.win_w) as u32, 24);
-            let depth_font_size = std::cmp::min((0.032 * ui.win_w) as u32, 28);
+            let alg_font_size = font_size(0.03 * ui.win_w);
 
             let (mut items, scrollbar) = widget::List::flow_down(search_results.len())
                 .item_size(1.6 * alg_font_size as conrod::Scalar)
@@ -370,17 +1302,45 @@ pub fn main() {
                 .padded_wh_of(ids.canvas_algorithms, 15.0)
                 .set(ids.list_algorithms, ui);
 
+            // The solver's solution is short but not known to be the shortest
+            let optimal = if solving { 0 } else { optimal_results(&search_results) };
+
             while let Some(item) = items.next(ui) {
                 let mut label = String::new();
                 let mut label_clone = String::new();
 
                 let button = match &search_results[item.i] {
                     &SearchResult::Algorithm(ref alg) => {
-                        for turn in alg {
-                            label.push_str(&format!(" {}", turn));
+                        if alg.is_empty() {
+                            label.push_str(" Already matches the goal");
+                        } else {
+                            // Copied without the brackets
+                            label_clone = format!(" {}", alg);
+                            label = if show_triggers {
+                                format!(" {}", format_triggers(alg))
+                            } else {
+                                label_clone.clone()
+                            };
+                        }
+
+                        if let Some(form) = commutator_form(alg) {
+                            label.push_str(&format!("  = {}", form));
+                        }
+
+                        if item.i < optimal && !alg.is_empty() {
+                            label.push_str("  - optimal");
                         }
 
-                        label_clone = label.clone();
+                        // The slice turn metric only differs with slice turns
+                        if !alg.is_empty() {
+                            label.push_str(&format!("  ({} HTM / {} QTM", alg.htm(), alg.qtm()));
+
+                            if alg.stm() != alg.htm() {
+                                label.push_str(&format!(" / {} STM", alg.stm()));
+                            }
+
+                            label.push_str(")");
+                        }
 
                         widget::Button::new()
                             .label(&label)
@@ -388,22 +1348,34 @@ pub fn main() {
                             .label_x(conrod::position::Relative::Align(
                                 conrod::position::Align::Start
                             ))
-                            .color(conrod::color::WHITE)
+                            .color(if preview == Some(item.i) {
+                                conrod::color::LIGHT_YELLOW
+                            } else {
+                                conrod::color::WHITE
+                            })
                             .border(0.0)
 
                     }
-                    &SearchResult::Depth(d) => {
-                        label.push_str(&format!("{}", d));
+                    &SearchResult::Stats { depth, found, nodes, elapsed_ms } => {
+                        label.push_str(&format!("depth {}: {} found, {} nodes, {}ms",
+                                                depth,
+                                                found,
+                                                format_count(nodes),
+                                                elapsed_ms));
 
                         widget::Button::new()
                             .label(&label)
-                            .label_color(conrod::color::LIGHT_BLUE)
-                            .label_font_size(depth_font_size)
+                            .label_color(conrod::color::GREY)
+                            .label_font_size(alg_font_size)
                             .border(0.0)
                     }
+                    // Depths and exhaustion are shown in the status line
+                    _ => continue,
                 };
 
                 if item.set(button, ui).was_clicked() && !label_clone.is_empty() {
+                    preview = if preview == Some(item.i) { None } else { Some(item.i) };
+
                     match clipboard.set_contents(label_clone) {
                         Ok(()) => {}
                         Err(e) => println!("Failed to copy to clipboard: {}", e),
@@ -425,9 +1397,40 @@ pub fn main() {
                 .cell_padding(face_padding, face_padding)
                 .set(ids.from_faces, ui);
 
-            fill_face(&mut from_faces, &mut from_colors, ui, current_color);
+            if fill_face(&mut from_faces, &mut from_colors, ui, current_color) {
+                edited = true;
+                editing_goal = false;
+            }
+
+            // An algorithm typed here is applied to the solved state as it is typed,
+            // in the empty corner of the net below the left face
+
+            for event in widget::TextBox::new(&typed_alg)
+                .w_h(1.8 * facedim, 0.4 * facedim)
+                .bottom_left_with_margin_on(ids.canvas_from, 0.1 * facedim)
+                .font_size(controls_font_size)
+                .border_color(if typed_alg_valid {
+                    conrod::color::BLACK
+                } else {
+                    conrod::color::DARK_RED
+                })
+                .set(ids.typed_alg, ui) {
+                if let widget::text_box::Event::Update(text) = event {
+                    // Unfinished turns leave the last valid state in place
+                    match parse_algorithm(&text) {
+                        Ok(alg) => {
+                            from_colors = from_cube(&solved.apply(&alg));
+                            edited = true;
+                            typed_alg_valid = true;
+                        }
+                        Err(_) => typed_alg_valid = false,
+                    }
+
+                    typed_alg = text;
+                }
+            }
 
-            // To
+            // To, or the previewed algorithm applied to the from state
 
             let mut to_faces = widget::Matrix::new(4, 3)
                 .middle_of(ids.canvas_to)
@@ -435,7 +1438,67 @@ pub fn main() {
                 .cell_padding(face_padding, face_padding)
                 .set(ids.to_faces, ui);
 
-            fill_face(&mut to_faces, &mut to_colors, ui, current_color);
+            match preview.map(|i| &search_results[i]) {
+                Some(&SearchResult::Algorithm(ref alg)) => {
+                    // Edits to the preview are thrown away
+                    let mut preview_colors = from_cube(&to_cube(&from_colors).apply(alg));
+                    fill_face(&mut to_faces, &mut preview_colors, ui, current_color);
+                }
+                _ => {
+                    if fill_face(&mut to_faces, &mut to_colors, ui, current_color) {
+                        edited = true;
+                        editing_goal = true;
+                    }
+                }
+            }
+
+            // Swapping searches the other way, highlighted while the states are swapped
+            // from how they were painted. In the empty corner of the net above the left face.
+
+            if widget::Button::new()
+                .w_h(1.8 * facedim, 0.4 * facedim)
+                .top_left_with_margin_on(ids.canvas_to, 0.1 * facedim)
+                .label("Swap with From")
+                .label_font_size(controls_font_size)
+                .color(if swapped {
+                    conrod::color::LIGHT_YELLOW
+                } else {
+                    conrod::color::WHITE
+                })
+                .set(ids.swap_button, ui)
+                .was_clicked() {
+                std::mem::swap(&mut from_colors, &mut to_colors);
+                swapped = !swapped;
+                preview = None;
+                edited = true;
+            }
+
+            // When the from state already matches the goal, searching only reports that
+            // unless this is on, in the empty corner of the net below the left face
+
+            for value in widget::Toggle::new(find_identities)
+                .w_h(1.8 * facedim, 0.4 * facedim)
+                .bottom_left_with_margin_on(ids.canvas_to, 0.1 * facedim)
+                .label("Find identities")
+                .label_font_size(controls_font_size)
+                .color(conrod::color::LIGHT_BLUE)
+                .set(ids.find_identities, ui) {
+                find_identities = value;
+            }
+
+            for value in widget::Toggle::new(show_triggers)
+                .w_h(1.8 * facedim, 0.4 * facedim)
+                .top_right_with_margin_on(ids.canvas_to, 0.1 * facedim)
+                .label("Show triggers")
+                .label_font_size(controls_font_size)
+                .color(conrod::color::LIGHT_BLUE)
+                .set(ids.show_triggers, ui) {
+                show_triggers = value;
+            }
+        }
+
+        if edited && (from_colors, to_colors) != before_edit {
+            history.push(before_edit);
         }
 
 
@@ -447,6 +1510,21 @@ pub fn main() {
             target.finish().unwrap();
         }
     }
+
+    if let Some(path) = config_path {
+        let config = Config {
+            width: ui.win_w as u32,
+            height: ui.win_h as u32,
+            scheme,
+            allowed_turns,
+            search_seconds,
+        };
+
+        match save_config(&path, &config) {
+            Ok(()) => {}
+            Err(e) => println!("Failed to save settings to {}: {}", path.display(), e),
+        }
+    }
 }
 
 fn fill_face(
@@ -454,7 +1532,10 @@ fn fill_face(
     piece_colors: &mut PieceColors,
     ui: &mut conrod::UiCell,
     current_color: conrod::Color
-) {
+) -> bool {
+    let before = *piece_colors;
+    let mut changed = false;
+
     let mut colors_list = [[None, None, Some(&mut piece_colors.back), None],
                            [Some(&mut piece_colors.down),
                             Some(&mut piece_colors.left),
@@ -468,12 +1549,157 @@ fn fill_face(
 
             while let Some(piece) = face.next(ui) {
                 let i = 3 * piece.row + piece.col;
+                let id = piece.widget_id;
 
-                if piece.set(widget::Button::new().color(colors[i]), ui)
+                // Right clicking erases the piece back to grey
+                let color = if piece.set(widget::Button::new().color(colors[i]), ui)
                     .was_clicked() {
-                    colors[i] = current_color;
+                    Some(current_color)
+                } else if ui.widget_input(id).clicks().right().next().is_some() {
+                    Some(conrod::color::GREY)
+                } else {
+                    None
+                };
+
+                if let Some(color) = color {
+                    if colors[i] != color {
+                        colors[i] = color;
+                        changed = true;
+                    }
                 }
             }
         }
     }
+
+    // Refuse painting more stickers of a color than a cube has, grey is never counted
+    let excess_before = to_cube(&before).excess_colors();
+
+    if to_cube(piece_colors).excess_colors().iter().any(|color| !excess_before.contains(color)) {
+        *piece_colors = before;
+        changed = false;
+    }
+
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_cube_round_trips() {
+        for len in 0..50 {
+            let (cube, _) = scramble(len, &mut rand::thread_rng());
+
+            assert_eq!(to_cube(&from_cube(&cube)), cube);
+        }
+    }
+
+    #[test]
+    fn quarter_turns_only_sends_no_half_turns() {
+        let cube = Cube::solved_state().turn_str("R U R' U'").unwrap();
+        let allowed: Vec<(Turn, bool)> = [Turn::R, Turn::R_, Turn::R2, Turn::U, Turn::U_, Turn::U2]
+            .iter()
+            .map(|&turn| (turn, true))
+            .collect();
+
+        let cancel = Arc::new(AtomicBool::new(false));
+        let (tx, rx) = channel();
+
+        spawn_search(cube,
+                     Cube::solved_state(),
+                     &allowed,
+                     true,
+                     SEARCH_SECONDS,
+                     cancel.clone(),
+                     tx);
+
+        let mut found = 0;
+
+        for res in rx.iter() {
+            match res {
+                SearchResult::Depth(8) => break,
+                SearchResult::Algorithm(alg) => {
+                    assert!(alg.iter().all(|turn| !turn.is_half_turn()), "{}", alg);
+                    found += 1;
+                }
+                _ => {}
+            }
+        }
+
+        cancel.store(true, Ordering::SeqCst);
+
+        assert!(found > 0);
+    }
+
+    #[test]
+    fn only_the_shortest_algorithms_are_optimal() {
+        // Only the up face has to be solved, which takes three turns, and turning U or D
+        // afterwards keeps it solved
+        let mut colors: [Color; 54] = Cube::solved_state().into();
+
+        for color in colors[9..].iter_mut() {
+            *color = Color::Grey;
+        }
+
+        let cube = Cube::solved_state().turn_str("R U' F").unwrap();
+        let (tx, rx) = channel();
+
+        search_bounded(cube, &Cube::from(colors), &ALL_TURNS[..18], 5, tx);
+
+        // Kept as the window keeps them
+        let results: Vec<SearchResult> = rx.iter()
+            .filter(|res| {
+                !matches!(*res,
+                          SearchResult::Depth(_) | SearchResult::Estimate { .. } |
+                          SearchResult::Exhausted(_) | SearchResult::TimedOut(_))
+            })
+            .collect();
+
+        let optimal = optimal_results(&results);
+        let lengths = |results: &[SearchResult]| -> Vec<usize> {
+            results.iter()
+                .filter_map(|res| match *res {
+                    SearchResult::Algorithm(ref alg) => Some(alg.len()),
+                    _ => None,
+                })
+                .collect()
+        };
+
+        assert_eq!(lengths(&results[..optimal]), vec![3]);
+        assert!(lengths(&results[optimal..]).iter().all(|&len| len > 3));
+        assert!(!lengths(&results[optimal..]).is_empty());
+    }
+
+    #[test]
+    fn flags_take_the_following_argument() {
+        let args: Vec<String> = ["--export", "out.txt", "--case", "sune.json"]
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect();
+
+        assert_eq!(flag_value(&args, "--case"), Some("sune.json".to_string()));
+        assert_eq!(flag_value(&args, "--export"), Some("out.txt".to_string()));
+        assert_eq!(flag_value(&args[..3], "--case"), None);
+        assert_eq!(flag_value(&args[2..], "--export"), None);
+    }
+
+    #[test]
+    fn every_scheme_can_be_solved() {
+        assert_eq!(Cube::solved_with(&SCHEMES[0].1), Cube::solved_state());
+
+        for &(name, ref scheme) in SCHEMES.iter() {
+            let solved = Cube::solved_with(scheme);
+            assert_eq!(in_solved_colors(&solved, scheme), Cube::solved_state(), "{}", name);
+
+            // Turns don't care about colors, so the solution solves the cube as colored
+            let (_, alg) = scramble(25, &mut rand::thread_rng());
+            let cube = solved.apply(&alg);
+            let solution = solve_cancellable(in_solved_colors(&cube, scheme),
+                                             &AtomicBool::new(false))
+                .unwrap();
+
+            assert!(cube.apply(&solution).is_solved_with(scheme), "{}", name);
+        }
+    }
 }
@@ -0,0 +1,496 @@
+use cube::*;
+
+use std::collections::VecDeque;
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// Kociemba's two-phase algorithm.
+// Phase one brings the cube into the group generated by U, D, L2, R2, F2 and B2,
+// where every piece is oriented and the E slice edges are in the E slice.
+// Phase two then solves the cube using only those turns.
+// Both phases are iterative deepening searches over coordinates, numbers describing
+// part of the cube, pruned by tables holding the distance to solved of pairs of them.
+
+// Phase one uses every outer turn, phase two only these
+const PHASE_TWO_TURNS: [Turn; 10] = [Turn::U, Turn::U_, Turn::U2, Turn::D, Turn::D_, Turn::D2,
+                                     Turn::L2, Turn::R2, Turn::F2, Turn::B2];
+
+// Phase one solutions are never longer than this, and phase two solutions 18 turns
+const MAX_PHASE_ONE_DEPTH: usize = 12;
+
+// How many phase one depths past the first solution to keep looking for shorter ones
+const EXTRA_PHASE_ONE_DEPTH: usize = 1;
+
+const TWISTS: usize = 2187;
+const FLIPS: usize = 2048;
+const SLICES: usize = 495;
+const CORNER_PERMUTATIONS: usize = 40320;
+const EDGE_PERMUTATIONS: usize = 40320;
+const SLICE_PERMUTATIONS: usize = 24;
+
+// The move and pruning tables take a moment to build. solve builds them once and keeps
+// them, while the tables of a solver made here are freed when it is dropped.
+pub struct Solver {
+    phase_one_turns: Vec<Cubies>,
+    phase_two_turns: Vec<Cubies>,
+    twist_moves: Vec<u16>,
+    flip_moves: Vec<u16>,
+    slice_moves: Vec<u16>,
+    corner_moves: Vec<u16>,
+    edge_moves: Vec<u16>,
+    slice_permutation_moves: Vec<u16>,
+    twist_slice_distances: Vec<u8>,
+    flip_slice_distances: Vec<u8>,
+    corner_slice_distances: Vec<u8>,
+    edge_slice_distances: Vec<u8>,
+}
+
+// The coordinates of phase one, all zero once it's done
+#[derive(Clone, Copy)]
+struct PhaseOne {
+    twist: usize,
+    flip: usize,
+    slice: usize,
+}
+
+// The coordinates of phase two, all zero once the cube is solved
+#[derive(Clone, Copy)]
+struct PhaseTwo {
+    corners: usize,
+    edges: usize,
+    slice: usize,
+}
+
+impl Default for Solver {
+    fn default() -> Self {
+        Solver::new()
+    }
+}
+
+// Solves a cube with every sticker colored as in Cube::solved_state.
+// None if the cube has grey stickers or can't be solved by turning.
+// The tables are built by the first call and kept for the following ones.
+pub fn solve(from: Cube) -> Option<Algorithm> {
+    solve_cancellable(from, &AtomicBool::new(false))
+}
+
+// Like solve, but gives up with None soon after cancel is set
+pub fn solve_cancellable(from: Cube, cancel: &AtomicBool) -> Option<Algorithm> {
+    static SOLVER: OnceLock<Solver> = OnceLock::new();
+
+    SOLVER.get_or_init(Solver::new).solve_cancellable(from, cancel)
+}
+
+impl Solver {
+    pub fn new() -> Self {
+        let solved = Cube::solved_state();
+        let turn_cubies = |turns: &[Turn]| -> Vec<Cubies> {
+            turns.iter().map(|&turn| solved.turn(turn).cubies().unwrap()).collect()
+        };
+
+        let phase_one_turns = turn_cubies(&ALL_TURNS[..18]);
+        let phase_two_turns = turn_cubies(&PHASE_TWO_TURNS);
+
+        let twist_moves = move_table(TWISTS, twist, &phase_one_turns);
+        let flip_moves = move_table(FLIPS, flip, &phase_one_turns);
+        let slice_moves = move_table(SLICES, slice, &phase_one_turns);
+        let corner_moves = move_table(CORNER_PERMUTATIONS, corner_permutation, &phase_two_turns);
+        let edge_moves = move_table(EDGE_PERMUTATIONS, edge_permutation, &phase_two_turns);
+        let slice_permutation_moves =
+            move_table(SLICE_PERMUTATIONS, slice_permutation, &phase_two_turns);
+
+        Solver {
+            twist_slice_distances: pruning_table(&twist_moves, TWISTS, &slice_moves, SLICES),
+            flip_slice_distances: pruning_table(&flip_moves, FLIPS, &slice_moves, SLICES),
+            corner_slice_distances: pruning_table(&corner_moves,
+                                                  CORNER_PERMUTATIONS,
+                                                  &slice_permutation_moves,
+                                                  SLICE_PERMUTATIONS),
+            edge_slice_distances: pruning_table(&edge_moves,
+                                                EDGE_PERMUTATIONS,
+                                                &slice_permutation_moves,
+                                                SLICE_PERMUTATIONS),
+            phase_one_turns,
+            phase_two_turns,
+            twist_moves,
+            flip_moves,
+            slice_moves,
+            corner_moves,
+            edge_moves,
+            slice_permutation_moves,
+        }
+    }
+
+    // Not the shortest solution, but usually one of 19 to 22 turns
+    pub fn solve(&self, from: Cube) -> Option<Algorithm> {
+        self.solve_cancellable(from, &AtomicBool::new(false))
+    }
+
+    pub fn solve_cancellable(&self, from: Cube, cancel: &AtomicBool) -> Option<Algorithm> {
+        let solved = Cube::solved_state();
+
+        // Outer turns never move the centers, so rotate them into place first
+        let rotation = rotations()
+            .into_iter()
+            .find(|rotation| same_centers(&from.apply(rotation), &solved))?;
+        let cube = from.apply(&rotation);
+
+        if !cube.is_solvable() {
+            return None;
+        }
+
+        let cubies = cube.cubies()?;
+        let start = PhaseOne {
+            twist: twist(&cubies),
+            flip: flip(&cubies),
+            slice: slice(&cubies),
+        };
+
+        let mut best = None;
+        let mut phase_one = Vec::with_capacity(MAX_PHASE_ONE_DEPTH);
+        let mut last_depth = MAX_PHASE_ONE_DEPTH;
+        let mut depth = 0;
+
+        while depth <= last_depth {
+            self.phase_one(&cubies, start, depth, &mut phase_one, &mut best, cancel);
+
+            if cancel.load(Ordering::Relaxed) {
+                return None;
+            }
+
+            if best.is_some() && last_depth == MAX_PHASE_ONE_DEPTH {
+                last_depth = (depth + EXTRA_PHASE_ONE_DEPTH).min(MAX_PHASE_ONE_DEPTH);
+            }
+
+            depth += 1;
+        }
+
+        let alg = rotation.into_iter().chain(best?).collect::<Algorithm>().simplify();
+
+        if from.apply(&alg).is_solved() {
+            Some(alg)
+        } else {
+            None
+        }
+    }
+
+    fn phase_one_distance(&self, coordinates: PhaseOne) -> usize {
+        let twist_slice = self.twist_slice_distances[coordinates.twist * SLICES +
+                                                     coordinates.slice];
+        let flip_slice = self.flip_slice_distances[coordinates.flip * SLICES + coordinates.slice];
+
+        twist_slice.max(flip_slice) as usize
+    }
+
+    fn phase_two_distance(&self, coordinates: PhaseTwo) -> usize {
+        let corner_slice = self.corner_slice_distances[coordinates.corners * SLICE_PERMUTATIONS +
+                                                       coordinates.slice];
+        let edge_slice = self.edge_slice_distances[coordinates.edges * SLICE_PERMUTATIONS +
+                                                   coordinates.slice];
+
+        corner_slice.max(edge_slice) as usize
+    }
+
+    // Tries every phase one solution of exactly the given depth,
+    // finishing each with a phase two solution shorter than the best found so far.
+    fn phase_one(
+        &self,
+        cubies: &Cubies,
+        coordinates: PhaseOne,
+        depth: usize,
+        turns: &mut Vec<usize>,
+        best: &mut Option<Algorithm>,
+        cancel: &AtomicBool,
+    ) {
+        // Phase two is quick, so checking here is soon enough
+        if cancel.load(Ordering::Relaxed) {
+            return;
+        }
+
+        if depth == 0 {
+            if coordinates.twist != 0 || coordinates.flip != 0 || coordinates.slice != 0 {
+                return;
+            }
+
+            // Ending on a phase two turn means a shorter phase one solution was already tried
+            if let Some(&last) = turns.last() {
+                if PHASE_TWO_TURNS.contains(&ALL_TURNS[last]) {
+                    return;
+                }
+            }
+
+            self.start_phase_two(cubies, turns, best);
+            return;
+        }
+
+        // Phase one is already done, continuing it only finds longer versions of that solution
+        let distance = self.phase_one_distance(coordinates);
+
+        if distance == 0 || distance > depth {
+            return;
+        }
+
+        let n = self.phase_one_turns.len();
+
+        for turn in 0..n {
+            if let Some(&last) = turns.last() {
+                if redundant_after(turn / 3, last / 3) {
+                    continue;
+                }
+            }
+
+            let next = PhaseOne {
+                twist: self.twist_moves[coordinates.twist * n + turn] as usize,
+                flip: self.flip_moves[coordinates.flip * n + turn] as usize,
+                slice: self.slice_moves[coordinates.slice * n + turn] as usize,
+            };
+
+            turns.push(turn);
+            self.phase_one(cubies, next, depth - 1, turns, best, cancel);
+            turns.pop();
+        }
+    }
+
+    fn start_phase_two(&self, cubies: &Cubies, phase_one: &[usize], best: &mut Option<Algorithm>) {
+        let max_depth = match *best {
+            Some(ref best) if best.len() <= phase_one.len() => return,
+            Some(ref best) => best.len() - phase_one.len() - 1,
+            None => 18,
+        };
+
+        let cubies = phase_one.iter()
+            .fold(*cubies, |cubies, &turn| multiply(&cubies, &self.phase_one_turns[turn]));
+        let start = PhaseTwo {
+            corners: corner_permutation(&cubies),
+            edges: edge_permutation(&cubies),
+            slice: slice_permutation(&cubies),
+        };
+
+        let mut phase_two = Vec::with_capacity(max_depth);
+
+        for depth in 0..max_depth + 1 {
+            if self.phase_two(start, depth, &mut phase_two) {
+                let alg = phase_one.iter()
+                    .map(|&turn| ALL_TURNS[turn])
+                    .chain(phase_two.iter().map(|&turn| PHASE_TWO_TURNS[turn]))
+                    .collect();
+
+                *best = Some(alg);
+                return;
+            }
+        }
+    }
+
+    // True if the cube is solved in exactly the given depth, leaving the solution in turns
+    fn phase_two(&self, coordinates: PhaseTwo, depth: usize, turns: &mut Vec<usize>) -> bool {
+        if depth == 0 {
+            return coordinates.corners == 0 && coordinates.edges == 0 && coordinates.slice == 0;
+        }
+
+        if self.phase_two_distance(coordinates) > depth {
+            return false;
+        }
+
+        let n = self.phase_two_turns.len();
+
+        for turn in 0..n {
+            if let Some(&last) = turns.last() {
+                if redundant_after(phase_two_face(turn), phase_two_face(last)) {
+                    continue;
+                }
+            }
+
+            let next = PhaseTwo {
+                corners: self.corner_moves[coordinates.corners * n + turn] as usize,
+                edges: self.edge_moves[coordinates.edges * n + turn] as usize,
+                slice: self.slice_permutation_moves[coordinates.slice * n + turn] as usize,
+            };
+
+            turns.push(turn);
+
+            if self.phase_two(next, depth - 1, turns) {
+                return true;
+            }
+
+            turns.pop();
+        }
+
+        false
+    }
+}
+
+// Faces numbered in the order U, D, L, R, F, B
+fn phase_two_face(turn: usize) -> usize {
+    if turn < 6 { turn / 3 } else { turn - 4 }
+}
+
+// Same face twice, or opposite faces in the other order, can be written shorter or earlier
+fn redundant_after(face: usize, last_face: usize) -> bool {
+    face == last_face || (face ^ 1 == last_face && face < last_face)
+}
+
+// The cubies after first doing a, then b
+fn multiply(a: &Cubies, b: &Cubies) -> Cubies {
+    let mut product = *a;
+
+    for i in 0..8 {
+        product.corners[i] = a.corners[b.corners[i]];
+        product.twists[i] = (a.twists[b.corners[i]] + b.twists[i]) % 3;
+    }
+
+    for i in 0..12 {
+        product.edges[i] = a.edges[b.edges[i]];
+        product.flips[i] = (a.flips[b.edges[i]] + b.flips[i]) % 2;
+    }
+
+    product
+}
+
+// The last twist follows from the others
+fn twist(cubies: &Cubies) -> usize {
+    cubies.twists[..7].iter().fold(0, |twist, &t| 3 * twist + t)
+}
+
+// The last flip follows from the others
+fn flip(cubies: &Cubies) -> usize {
+    cubies.flips[..11].iter().fold(0, |flip, &f| 2 * flip + f)
+}
+
+// Which four positions hold the E slice edges FR, FL, BL and BR, zero when they're in the slice
+fn slice(cubies: &Cubies) -> usize {
+    let (mut slice, mut seen) = (0, 0);
+
+    for (i, &edge) in cubies.edges.iter().enumerate().rev() {
+        if edge >= 8 {
+            seen += 1;
+            slice += binomial(11 - i, seen);
+        }
+    }
+
+    slice
+}
+
+fn corner_permutation(cubies: &Cubies) -> usize {
+    permutation_rank(&cubies.corners)
+}
+
+// Only meaningful in phase two, where the U and D layer edges stay out of the E slice
+fn edge_permutation(cubies: &Cubies) -> usize {
+    permutation_rank(&cubies.edges[..8])
+}
+
+fn slice_permutation(cubies: &Cubies) -> usize {
+    permutation_rank(&cubies.edges[8..])
+}
+
+// The position of the permutation in lexicographic order, zero when sorted
+fn permutation_rank(pieces: &[usize]) -> usize {
+    (0..pieces.len()).fold(0, |rank, i| {
+        let smaller = pieces[i + 1..].iter().filter(|&&piece| piece < pieces[i]).count();
+
+        rank * (pieces.len() - i) + smaller
+    })
+}
+
+fn binomial(n: usize, k: usize) -> usize {
+    if k > n {
+        return 0;
+    }
+
+    (0..k).fold(1, |product, i| product * (n - i) / (i + 1))
+}
+
+// Where each turn takes every value of a coordinate, indexed by value times turns plus turn.
+// Every value must be reachable from the solved cube using the turns.
+fn move_table(size: usize, coordinate: fn(&Cubies) -> usize, turns: &[Cubies]) -> Vec<u16> {
+    let solved = Cube::solved_state().cubies().unwrap();
+
+    let mut table = vec![0; size * turns.len()];
+    let mut seen = vec![false; size];
+    let mut queue = VecDeque::new();
+
+    seen[coordinate(&solved)] = true;
+    queue.push_back(solved);
+
+    while let Some(cubies) = queue.pop_front() {
+        let value = coordinate(&cubies);
+
+        for (i, turn) in turns.iter().enumerate() {
+            let next = multiply(&cubies, turn);
+            let next_value = coordinate(&next);
+
+            table[value * turns.len() + i] = next_value as u16;
+
+            if !seen[next_value] {
+                seen[next_value] = true;
+                queue.push_back(next);
+            }
+        }
+    }
+
+    table
+}
+
+// Turns needed to bring both coordinates to zero, indexed by first times second size plus second
+fn pruning_table(first_moves: &[u16], first_size: usize, second_moves: &[u16], second_size: usize)
+                 -> Vec<u8> {
+    let turns = first_moves.len() / first_size;
+
+    let mut distances = vec![u8::MAX; first_size * second_size];
+    let mut queue = VecDeque::new();
+
+    distances[0] = 0;
+    queue.push_back((0, 0));
+
+    while let Some((first, second)) = queue.pop_front() {
+        let distance = distances[first * second_size + second];
+
+        for turn in 0..turns {
+            let next_first = first_moves[first * turns + turn] as usize;
+            let next_second = second_moves[second * turns + turn] as usize;
+            let next = next_first * second_size + next_second;
+
+            if distances[next] == u8::MAX {
+                distances[next] = distance + 1;
+                queue.push_back((next_first, next_second));
+            }
+        }
+    }
+
+    distances
+}
+
+fn same_centers(a: &Cube, b: &Cube) -> bool {
+    let center = |face: u32| (face >> 12) & 0b111;
+
+    a.faces().iter().zip(b.faces().iter()).all(|(&a, &b)| center(a) == center(b))
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate rand;
+
+    use super::*;
+
+    #[test]
+    fn solves_random_scrambles() {
+        let mut rng = rand::thread_rng();
+
+        for _ in 0..100 {
+            let (cube, scramble) = scramble(30, &mut rng);
+            let alg = solve(cube).unwrap();
+
+            assert!(cube.apply(&alg).is_solved(), "{} doesn't solve {}", alg, scramble);
+            // Both phases together never take more than this
+            assert!(alg.len() <= MAX_PHASE_ONE_DEPTH + 18, "{} is too long", alg);
+        }
+    }
+
+    #[test]
+    fn cancelled_solve_gives_up() {
+        let (cube, _) = scramble(30, &mut rand::thread_rng());
+
+        assert!(solve_cancellable(cube, &AtomicBool::new(true)).is_none());
+        assert!(solve_cancellable(cube, &AtomicBool::new(false)).is_some());
+    }
+}